@@ -4,6 +4,8 @@ use wgpu::naga::{self, FastHashMap, Span};
 pub enum PreprocessError {
     SyntaxError,
     Missing(String),
+    /// Define not supported by the pipeline using the shader.
+    UnsupportedDefine(String),
 }
 
 #[derive(Debug)]
@@ -48,6 +50,7 @@ impl Debug for PreprocessError {
         match self {
             Self::SyntaxError => write!(f, "SyntaxError"),
             Self::Missing(import) => write!(f, "Missing import: '{}'", import),
+            Self::UnsupportedDefine(define) => write!(f, "Unsupported define: '{}'", define),
         }
     }
 }
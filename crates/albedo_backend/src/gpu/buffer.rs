@@ -1,5 +1,9 @@
 use bytemuck::Pod;
-use std::{convert::TryFrom, marker::PhantomData, ops::RangeBounds};
+use std::{
+    convert::TryFrom,
+    marker::PhantomData,
+    ops::{Range, RangeBounds},
+};
 use wgpu::util::DeviceExt;
 
 use crate::mesh::IndexData;
@@ -199,6 +203,19 @@ impl<T: Pod> Buffer<T> {
         queue.write_buffer(&self.inner, 0, slice);
     }
 
+    /// Upload the byte range `range` of `content`, at the same offset in the buffer.
+    pub fn update_range(&mut self, queue: &wgpu::Queue, range: Range<u64>, content: &[T]) {
+        if range.is_empty() {
+            return;
+        }
+        let slice: &[u8] = bytemuck::cast_slice(content);
+        queue.write_buffer(
+            &self.inner,
+            range.start,
+            &slice[range.start as usize..range.end as usize],
+        );
+    }
+
     pub fn count(&self) -> u64 {
         self.inner.count()
     }
@@ -2,6 +2,7 @@ mod buffer;
 mod pipeline;
 mod primitive;
 mod queries;
+mod readback;
 mod resource;
 mod texture_atlas;
 mod vertex_buffer;
@@ -10,6 +11,7 @@ pub use buffer::*;
 pub use pipeline::*;
 pub use primitive::*;
 pub use queries::*;
+pub use readback::*;
 pub use resource::*;
 pub use texture_atlas::*;
 pub use vertex_buffer::{AsVertexBufferLayout, VertexBufferLayoutBuilder};
@@ -0,0 +1,217 @@
+use bytemuck::Pod;
+
+use crate::Alignment2D;
+
+#[derive(Debug)]
+pub enum ReadbackError {
+    /// Compressed and combined depth / stencil formats can't be read back.
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// The size of a texel isn't a multiple of the requested type.
+    TypeMismatch {
+        texel_size: u32,
+        type_size: usize,
+    },
+    Map(wgpu::BufferAsyncError),
+}
+
+impl std::fmt::Display for ReadbackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => write!(f, "Unsupported format: {:?}", format),
+            Self::TypeMismatch {
+                texel_size,
+                type_size,
+            } => write!(
+                f,
+                "Texel of {} bytes can't be read as type of {} bytes",
+                texel_size, type_size
+            ),
+            Self::Map(e) => write!(f, "Failed to map buffer: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReadbackError {}
+
+/// Copy of one layer of the first mip of a texture, read back asynchronously.
+///
+/// This works on every platform, including the web where the device can't be
+/// polled until the data is mapped, see [`read_texture`] for a blocking helper.
+///
+/// `T` is the type of one texel component, or of the entire texel,
+/// e.g., `f32` or `[f32; 4]` for a [`wgpu::TextureFormat::Rgba32Float`] texture.
+pub struct TextureReadback<T> {
+    buffer: wgpu::Buffer,
+    alignment: Alignment2D,
+    height: u32,
+    _type: std::marker::PhantomData<T>,
+}
+
+impl<T: Pod> TextureReadback<T> {
+    /// Record the copy of `layer` into `encoder`.
+    ///
+    /// The texture must be created with [`wgpu::TextureUsages::COPY_SRC`].
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        layer: u32,
+    ) -> Result<Self, ReadbackError> {
+        let format = texture.format();
+        let texel_size = match format.block_copy_size(None) {
+            Some(size) if format.block_dimensions() == (1, 1) => size,
+            _ => return Err(ReadbackError::UnsupportedFormat(format)),
+        };
+        let type_size = std::mem::size_of::<T>();
+        if type_size == 0 || !(texel_size as usize).is_multiple_of(type_size) {
+            return Err(ReadbackError::TypeMismatch {
+                texel_size,
+                type_size,
+            });
+        }
+
+        let (width, height) = (texture.width(), texture.height());
+        let alignment = Alignment2D::texture_buffer_copy(width as usize, texel_size as usize);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Texture Readback Buffer"),
+            size: height as u64 * alignment.padded_bytes() as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(alignment.padded_bytes() as u32),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(Self {
+            buffer,
+            alignment,
+            height,
+            _type: std::marker::PhantomData,
+        })
+    }
+
+    /// Map the copy, and call `callback` with the tightly packed texels.
+    ///
+    /// The encoder passed to [`TextureReadback::new`] must be submitted first.
+    /// The callback runs once the device is polled natively, or from the event
+    /// loop of the browser on the web.
+    pub fn read<F>(self, callback: F)
+    where
+        F: FnOnce(Result<Vec<T>, ReadbackError>) + wgpu::WasmNotSend + 'static,
+    {
+        let Self {
+            buffer,
+            alignment,
+            height,
+            ..
+        } = self;
+        let mapped = buffer.clone();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let data = result.map_err(ReadbackError::Map).map(|_| {
+                    let data = unpad_rows(&mapped.slice(..).get_mapped_range(), &alignment, height);
+                    mapped.unmap();
+                    data
+                });
+                callback(data);
+            });
+    }
+}
+
+/// Remove the padding at the end of each row of `padded`.
+fn unpad_rows<T: Pod>(padded: &[u8], alignment: &Alignment2D, height: u32) -> Vec<T> {
+    let mut data =
+        Vec::with_capacity(alignment.bytes() / std::mem::size_of::<T>() * height as usize);
+    // Rows are aligned on `COPY_BYTES_PER_ROW_ALIGNMENT`, and can be cast in place.
+    for row in padded.chunks_exact(alignment.padded_bytes()) {
+        data.extend_from_slice(bytemuck::cast_slice(&row[..alignment.bytes()]));
+    }
+    data
+}
+
+/// Read one layer of the first mip of `texture` into a tightly packed `Vec`.
+///
+/// The texture must be created with [`wgpu::TextureUsages::COPY_SRC`].
+/// This submits a copy to `queue` and blocks until the data is mapped.
+///
+/// Blocking isn't possible on the web, use [`TextureReadback`] instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_texture<T: Pod + Send>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    layer: u32,
+) -> Result<Vec<T>, ReadbackError> {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Texture Readback Encoder"),
+    });
+    let readback = TextureReadback::<T>::new(device, &mut encoder, texture, layer)?;
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    readback.read(move |result| {
+        sender.send(result).unwrap();
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().expect("map callback dropped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpad_rows_matches_alignment() {
+        let height = 3;
+        // Row sizes below, above, and at a multiple of the 256 bytes alignment.
+        for (width, texel_size) in [(1, 4), (7, 16), (65, 4), (100, 8), (64, 4)]
+            .iter()
+            .copied()
+        {
+            let alignment = Alignment2D::texture_buffer_copy(width, texel_size);
+            assert!(alignment.padded_bytes() >= alignment.bytes());
+            assert_eq!(
+                alignment.padded_bytes() % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize,
+                0
+            );
+
+            let words = alignment.bytes() / 4;
+            let mut padded = vec![u32::MAX; alignment.padded_bytes() / 4 * height as usize];
+            let mut expected = Vec::new();
+            for (y, row) in padded
+                .chunks_exact_mut(alignment.padded_bytes() / 4)
+                .enumerate()
+            {
+                for (x, value) in row[..words].iter_mut().enumerate() {
+                    *value = (y * words + x) as u32;
+                    expected.push(*value);
+                }
+            }
+
+            let data: Vec<u32> = unpad_rows(bytemuck::cast_slice(&padded), &alignment, height);
+            assert_eq!(data, expected, "width {}, texel size {}", width, texel_size);
+        }
+    }
+}
@@ -66,6 +66,7 @@ impl AttributeId {
     pub const POSITION: AttributeId = AttributeId { 0: "POSITION" };
     pub const NORMAL: AttributeId = AttributeId { 0: "NORMAL" };
     pub const TEX_COORDS_0: AttributeId = AttributeId { 0: "TEX_COORDS_0" };
+    pub const TANGENT: AttributeId = AttributeId { 0: "TANGENT" };
 }
 
 pub struct AttributeDescriptor {
@@ -98,6 +99,13 @@ impl AttributeDescriptor {
             format,
         }
     }
+
+    pub fn tangent(format: wgpu::VertexFormat) -> Self {
+        Self {
+            id: AttributeId::TANGENT,
+            format,
+        }
+    }
 }
 
 enum AttributeData {
@@ -0,0 +1,77 @@
+// Stream compaction of the rays that aren't terminated.
+//
+// Written in WGSL: the GLSL frontend of naga (24.0) has no atomic builtins,
+// and rejects `atomicAdd` with "Unknown function", on storage buffers and
+// shared variables alike. Both are needed by the compaction.
+
+// Must match `RayPayload` in `structures.glsl`.
+struct RayPayload {
+  origin: vec4<f32>,
+  dir: vec4<f32>,
+  radiance: vec4<f32>,
+  terminated: vec4<u32>,
+  specular: vec4<f32>,
+};
+
+// Must match `ActiveRayBuffer` in the compacted shaders.
+struct ActiveRays {
+  count: atomic<u32>,
+  rowSize: u32,
+  padding: vec2<u32>,
+  indices: array<u32>,
+};
+
+@group(0) @binding(0) var<storage, read> rays: array<RayPayload>;
+@group(0) @binding(1) var<storage, read_write> activeRays: ActiveRays;
+@group(0) @binding(2) var<storage, read_write> dispatchArgs: array<u32, 3>;
+
+// Must match the workgroup size of the compacted shaders.
+const WORKGROUP_INVOCATIONS: u32 = 64u;
+const MAX_WORKGROUPS: u32 = 65535u;
+
+var<workgroup> workgroupCount: atomic<u32>;
+var<workgroup> workgroupStart: u32;
+
+@compute @workgroup_size(8, 8)
+fn compact(
+  @builtin(global_invocation_id) id: vec3<u32>,
+  @builtin(num_workgroups) workgroups: vec3<u32>,
+  @builtin(local_invocation_index) localIndex: u32,
+) {
+  if (localIndex == 0u) {
+    atomicStore(&workgroupCount, 0u);
+  }
+  workgroupBarrier();
+
+  let rowSize = 8u * workgroups.x;
+  let index = id.y * rowSize + id.x;
+  let alive = index < arrayLength(&rays) && rays[index].terminated.x == 0u;
+  var offset = 0u;
+  if (alive) {
+    offset = atomicAdd(&workgroupCount, 1u);
+  }
+  workgroupBarrier();
+
+  // A single global atomic per workgroup.
+  if (localIndex == 0u) {
+    workgroupStart = atomicAdd(&activeRays.count, atomicLoad(&workgroupCount));
+    if (index == 0u) {
+      activeRays.rowSize = rowSize;
+    }
+  }
+  workgroupBarrier();
+
+  if (alive) {
+    activeRays.indices[workgroupStart + offset] = index;
+  }
+}
+
+// Writes the indirect dispatch size, must run after `compact`.
+@compute @workgroup_size(1)
+fn dispatch_size() {
+  let groups = (atomicLoad(&activeRays.count) + WORKGROUP_INVOCATIONS - 1u) / WORKGROUP_INVOCATIONS;
+  let x = min(groups, MAX_WORKGROUPS);
+  dispatchArgs[0] = x;
+  dispatchArgs[1] = select(1u, (groups + x - 1u) / x, x > 0u);
+  dispatchArgs[2] = 1u;
+}
@@ -31,4 +31,20 @@ float luminance(vec3 color) {
   return dot(color, vec3(0.2126, 0.7152, 0.0722));
 }
 
+vec3 RGBToYCoCg(vec3 color) {
+  return vec3(
+    dot(color, vec3(0.25, 0.5, 0.25)),
+    dot(color, vec3(0.5, 0.0, -0.5)),
+    dot(color, vec3(-0.25, 0.5, -0.25))
+  );
+}
+
+vec3 YCoCgToRGB(vec3 color) {
+  return vec3(
+    color.x + color.y - color.z,
+    color.x + color.z,
+    color.x - color.y - color.z
+  );
+}
+
 #endif // COLORSPACE_H
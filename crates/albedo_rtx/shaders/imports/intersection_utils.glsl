@@ -2,6 +2,8 @@
 #define INTERSECTION_UTILS_H
 
 #define STACK_SIZE 32
+// Must match `TLAS::STACK_SIZE`.
+#define TLAS_STACK_SIZE 64
 // #define DEBUG_CWBVH_TRAVERSAL
 
 struct Primitive
@@ -54,6 +56,41 @@ vec3 barycentricCoordinates(vec2 uv)
   return vec3(1.0 - uv.x - uv.y, uv);
 }
 
+#ifdef ALPHA_TEST
+// Material and vertex start of the instance being traversed.
+// `alphaTestMaterial` is `INVALID_UINT` for opaque instances.
+uint alphaTestMaterial;
+uint alphaTestVertexStart;
+
+/**
+ * Returns `true` if the hit passes the alpha cutoff of the material.
+ *
+ * @param primitive Index of the primitive in the source mesh
+ * @param uv Barycentric coordinates of the hit
+ */
+bool
+alphaTestHit(uint primitive, vec2 uv)
+{
+  Material material = materials[alphaTestMaterial];
+  float alpha = material.color.a;
+  if (material.albedoTexture != INVALID_UINT)
+  {
+    uint index = primitive * 3;
+    Vertex v0 = getVertex(alphaTestVertexStart, index);
+    Vertex v1 = getVertex(alphaTestVertexStart, index + 1);
+    Vertex v2 = getVertex(alphaTestVertexStart, index + 2);
+    vec2 texCoord = interpolateBarycentric(
+      vec2(v0.position.w, v0.normal.w),
+      vec2(v1.position.w, v1.normal.w),
+      vec2(v2.position.w, v2.normal.w),
+      barycentricCoordinates(uv)
+    );
+    alpha *= fetchTexture(material.albedoTexture, texCoord).a;
+  }
+  return alpha >= material.alphaCutoff;
+}
+#endif
+
 Ray
 transformRay(inout Ray ray, mat4 transform)
 {
@@ -61,6 +98,7 @@ transformRay(inout Ray ray, mat4 transform)
   // @todo: radiance and throughput should go somewhere else.
   result.origin = transformPosition(ray.origin, transform);
   result.dir = transformDirection(ray.dir, transform);
+  result.mask = ray.mask;
   return result;
 }
 
@@ -85,6 +123,95 @@ intersectPlane(Ray ray, vec3 normal, vec3 origin, vec3 edge01, vec3 edge02)
   return t;
 }
 
+/**
+ * Slab test against an AABB
+ *
+ * @return Entry distance, or MAX_FLOAT if the box is missed or further than `t`
+ */
+float
+intersectAABB(vec3 origin, vec3 inverseDir, vec3 boxMin, vec3 boxMax, float t)
+{
+  vec3 t0 = (boxMin - origin) * inverseDir;
+  vec3 t1 = (boxMax - origin) * inverseDir;
+  vec3 tmin = min(t0, t1);
+  vec3 tmax = max(t0, t1);
+  float near = max(max(max(tmin.x, tmin.y), tmin.z), 0.0);
+  float far = min(min(min(tmax.x, tmax.y), tmax.z), t);
+  return near <= far ? near : MAX_FLOAT;
+}
+
+/**
+ * Returns the closest positive distance to a sphere, or `MAX_FLOAT`.
+ *
+ * `dir` doesn't need to be normalized.
+ */
+float
+intersectSphere(vec3 origin, vec3 dir, vec3 center, float radius)
+{
+  vec3 oc = origin - center;
+  float a = dot(dir, dir);
+  float b = dot(oc, dir);
+  float c = dot(oc, oc) - radius * radius;
+  float discriminant = b * b - a * c;
+  if (discriminant < 0.0) return MAX_FLOAT;
+
+  float s = sqrt(discriminant);
+  float t = (-b - s) / a;
+  if (t <= EPSILON) t = (-b + s) / a;
+  return t > EPSILON ? t : MAX_FLOAT;
+}
+
+/**
+ * Returns the closest positive distance to a box, or `MAX_FLOAT`.
+ *
+ * At the opposite of `intersectAABB`, rays starting inside the box hit its faces.
+ */
+float
+intersectBox(vec3 origin, vec3 inverseDir, vec3 boxMin, vec3 boxMax)
+{
+  vec3 t0 = (boxMin - origin) * inverseDir;
+  vec3 t1 = (boxMax - origin) * inverseDir;
+  vec3 tmin = min(t0, t1);
+  vec3 tmax = max(t0, t1);
+  float near = max(max(tmin.x, tmin.y), tmin.z);
+  float far = min(min(tmax.x, tmax.y), tmax.z);
+  if (near > far || far <= EPSILON) return MAX_FLOAT;
+  return near > EPSILON ? near : far;
+}
+
+float
+intersectProcedural(ProceduralPrimitive primitive, vec3 origin, vec3 dir)
+{
+  if (primitive.kind == PROCEDURAL_SPHERE)
+  {
+    vec3 center = (primitive.min + primitive.max) * 0.5;
+    float radius = (primitive.max.x - primitive.min.x) * 0.5;
+    return intersectSphere(origin, dir, center, radius);
+  }
+  return intersectBox(origin, vec3(1.0) / dir, primitive.min, primitive.max);
+}
+
+/**
+ * Normal of a procedural primitive, in model space.
+ *
+ * @param position Hit position, in model space
+ */
+vec3
+proceduralNormal(ProceduralPrimitive primitive, vec3 position)
+{
+  vec3 center = (primitive.min + primitive.max) * 0.5;
+  if (primitive.kind == PROCEDURAL_SPHERE)
+  {
+    return normalize(position - center);
+  }
+  // Select the axis along which the hit is the closest to a face.
+  vec3 local = (position - center) / max(primitive.max - center, vec3(EPSILON));
+  vec3 a = abs(local);
+  if (a.x >= a.y && a.x >= a.z) return vec3(sign(local.x), 0.0, 0.0);
+  if (a.y >= a.z) return vec3(0.0, sign(local.y), 0.0);
+  return vec3(0.0, 0.0, sign(local.z));
+}
+
 uint
 sign_extend_s8x4(uint i)
 {
@@ -115,9 +242,9 @@ as_uchar4(float val)
  */
 vec4
 #ifndef DEBUG_CWBVH_TRAVERSAL
-traverse_cwbvh(Ray ray, uint bvhNodeStart, uint primitiveStart, float t)
+traverse_cwbvh(Ray ray, uint bvhNodeStart, uint primitiveStart, bool procedural, float t)
 #else
-traverse_cwbvh(Ray ray, uint bvhNodeStart, uint primitiveStart, float t, inout uint stepCount)
+traverse_cwbvh(Ray ray, uint bvhNodeStart, uint primitiveStart, bool procedural, float t, inout uint stepCount)
 #endif
 {
 	const vec4 O4 = vec4(ray.origin, 1.0);
@@ -272,6 +399,18 @@ traverse_cwbvh(Ray ray, uint bvhNodeStart, uint primitiveStart, float t, inout u
 			int triangleIndex = findMSB( tgroup.y );
 			tgroup.y -= 1 << triangleIndex;
 
+			if (procedural)
+			{
+				// `tgroup.x` is expressed in `vec4`, with three `vec4` per primitive.
+				uint slot = tgroup.x / 3 + triangleIndex;
+				float d = intersectProcedural(procedurals[primitiveStart + slot], O4.xyz, D4.xyz);
+				if (d >= tmax) continue;
+				uv = vec2(0.0);
+				tmax = d;
+				hitAddr = slot;
+				continue;
+			}
+
 			uint triAddr = tgroup.x + (primitiveStart + triangleIndex) * 3;
 
 			vec3 e1 = trianglesCWBVH[triAddr].xyz;
@@ -289,6 +428,9 @@ traverse_cwbvh(Ray ray, uint bvhNodeStart, uint primitiveStart, float t, inout u
 			if (v < EPSILON || u + v > EPSILON1) continue;
 			float d = f * dot( e1, q );
 			if (d <= EPSILON || d >= tmax) continue;
+			#ifdef ALPHA_TEST
+			if (alphaTestMaterial != INVALID_UINT && !alphaTestHit(floatBitsToUint( v0.w ), vec2(u, v))) continue;
+			#endif
 			uv = vec2(u, v);
 			tmax = d;
 			hitAddr = floatBitsToUint( v0.w );
@@ -311,43 +453,117 @@ traverse_cwbvh(Ray ray, uint bvhNodeStart, uint primitiveStart, float t, inout u
 	return hit;
 }
 
+/**
+ * Closest hit of `ray` with the instances, ignoring hits further than `tMax`.
+ *
+ * `intersection.index` is `INVALID_UINT` if nothing is hit.
+ */
 Intersection
-sceneHit(Ray ray)
+sceneHit(Ray ray, float tMax)
 {
   #ifdef DEBUG_CWBVH_TRAVERSAL
   uint stepCount = 0;
   #endif
 
   Intersection intersection;
-  intersection.dist = MAX_FLOAT;
+  intersection.dist = tMax;
   intersection.index = INVALID_UINT;
   intersection.instance = INVALID_UINT;
   intersection.emitter = INVALID_UINT;
 
-  for (uint i = 0; i < instances.length(); ++i)
+  const vec3 inverseDir = vec3(1.0) / ray.dir;
+
+  // Traverse the TLAS, visiting the nearest child first.
+  uint stack[TLAS_STACK_SIZE];
+  // Entry distance of the stacked nodes, to skip them once a closer hit is found.
+  float stackDist[TLAS_STACK_SIZE];
+  uint stackPtr = 0;
+  uint nodeIndex = 0;
+
+  if (intersectAABB(ray.origin, inverseDir, tlasNodes[0].min, tlasNodes[0].max, intersection.dist) == MAX_FLOAT)
+  {
+    return intersection;
+  }
+
+  while (true)
   {
-    Instance instance = instances[i];
-
-    // Performs intersection in model space.
-    Ray rayModel = transformRay(ray, instance.worldToModel);
-	#ifndef DEBUG_CWBVH_TRAVERSAL
-	vec4 hit = traverse_cwbvh(rayModel, instance.bvhRootIndex, instance.primitiveRootIndex, intersection.dist);
-	#else
-	vec4 hit = traverse_cwbvh(rayModel, instance.bvhRootIndex, instance.primitiveRootIndex, intersection.dist, stepCount);
-	#endif
-	if (hit.x < intersection.dist)
+    TLASNode node = tlasNodes[nodeIndex];
+    if (node.instanceCount > 0)
     {
-		intersection.dist = hit.x;
-		intersection.uv = hit.yz;
-		intersection.index = floatBitsToUint(hit.w) * 3;
-		intersection.instance = i;
-		intersection.emitter = INVALID_UINT;
-		intersection.materialIndex = instance.materialIndex;
+      uint i = node.leftFirst;
+      Instance instance = instances[i];
+
+      // Performs intersection in model space.
+      Ray rayModel = transformRay(ray, instance.worldToModel);
+      bool procedural = (instance.flags & INSTANCE_PROCEDURAL) != 0u;
+      vec4 hit = vec4(MAX_FLOAT);
+      if ((instance.mask & ray.mask) != 0u)
+      {
+        #ifdef ALPHA_TEST
+        // Opaque instances keep the fast path.
+        alphaTestMaterial = INVALID_UINT;
+        if ((instance.flags & (INSTANCE_OPAQUE | INSTANCE_PROCEDURAL)) == 0u && materials[instance.materialIndex].alphaCutoff > 0.0)
+        {
+          alphaTestMaterial = instance.materialIndex;
+          alphaTestVertexStart = instance.vertexRootIndex;
+        }
+        #endif
+        #ifndef DEBUG_CWBVH_TRAVERSAL
+        hit = traverse_cwbvh(rayModel, instance.bvhRootIndex, instance.primitiveRootIndex, procedural, intersection.dist);
+        #else
+        hit = traverse_cwbvh(rayModel, instance.bvhRootIndex, instance.primitiveRootIndex, procedural, intersection.dist, stepCount);
+        #endif
+      }
+      if (hit.x < intersection.dist)
+      {
+        intersection.dist = hit.x;
+        intersection.uv = hit.yz;
+        // Procedural hits index the primitive, triangle hits its first vertex.
+        intersection.index = procedural ? floatBitsToUint(hit.w) : floatBitsToUint(hit.w) * 3;
+        intersection.instance = i;
+        intersection.emitter = INVALID_UINT;
+        intersection.materialIndex = instance.materialIndex;
+      }
     }
+    else
+    {
+      uint left = node.leftFirst;
+      uint right = node.leftFirst + 1;
+      float distLeft = intersectAABB(ray.origin, inverseDir, tlasNodes[left].min, tlasNodes[left].max, intersection.dist);
+      float distRight = intersectAABB(ray.origin, inverseDir, tlasNodes[right].min, tlasNodes[right].max, intersection.dist);
+      if (distLeft > distRight)
+      {
+        float tmpDist = distLeft; distLeft = distRight; distRight = tmpDist;
+        uint tmpIndex = left; left = right; right = tmpIndex;
+      }
+
+      if (distLeft != MAX_FLOAT)
+      {
+        nodeIndex = left;
+        // The depth of the tree is checked on the CPU, and the stack can't overflow.
+        if (distRight != MAX_FLOAT && stackPtr < TLAS_STACK_SIZE)
+        {
+          stack[stackPtr] = right;
+          stackDist[stackPtr++] = distRight;
+        }
+        continue;
+      }
+    }
+
+    // Skip the nodes entered further than the closest hit found since they were pushed.
+    while (stackPtr > 0 && stackDist[stackPtr - 1] > intersection.dist) { --stackPtr; }
+    if (stackPtr == 0) break;
+    nodeIndex = stack[--stackPtr];
   }
   return intersection;
 }
 
+Intersection
+sceneHit(Ray ray)
+{
+  return sceneHit(ray, MAX_FLOAT);
+}
+
 #ifdef DEBUG_CWBVH_TRAVERSAL
 uint
 sceneTraversal(Ray ray)
@@ -356,8 +572,10 @@ sceneTraversal(Ray ray)
 	for (uint i = 0; i < instances.length(); ++i)
   	{
 		Instance instance = instances[i];
+		if ((instance.mask & ray.mask) == 0u) continue;
 		Ray rayModel = transformRay(ray, instance.worldToModel);
-		traverse_cwbvh(rayModel, instance.bvhRootIndex, instance.primitiveRootIndex, MAX_FLOAT, stepCount);
+		bool procedural = (instance.flags & INSTANCE_PROCEDURAL) != 0u;
+		traverse_cwbvh(rayModel, instance.bvhRootIndex, instance.primitiveRootIndex, procedural, MAX_FLOAT, stepCount);
 	}
 	return stepCount;
 }
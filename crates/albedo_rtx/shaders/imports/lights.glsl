@@ -0,0 +1,204 @@
+#ifndef LIGHTS_H
+#define LIGHTS_H
+
+/**
+ * Light sampling.
+ *
+ * Rectangle lights emit on the side of `normal`, `tangent` and `bitangent`
+ * are the edges of the rectangle, and the position is its corner.
+ *
+ * Point, spot, and directional lights without angular diameter are delta
+ * lights: they can't be hit by BSDF samples, and aren't weighted with MIS.
+ *
+ * Requires `intersection_utils.glsl` and `math.glsl`.
+ */
+
+struct LightSample
+{
+  vec3 dir;
+  float dist;
+  vec3 radiance;
+  // Solid angle pdf, `1.0` for delta lights.
+  float pdf;
+  bool delta;
+};
+
+vec3
+lightOrigin(Light light)
+{
+  return vec3(light.normal.w, light.tangent.w, light.bitangent.w);
+}
+
+float
+directionalSolidAngle(Light light)
+{
+  return TWO_PI * (1.0 - light.cosOuter);
+}
+
+bool
+isDeltaLight(Light light)
+{
+  if (light.kind == LIGHT_DIRECTIONAL)
+  {
+    return directionalSolidAngle(light) < EPSILON;
+  }
+  return light.kind != LIGHT_RECT;
+}
+
+/**
+ * Radiance of a non-delta light, seen from any direction.
+ */
+vec3
+lightRadiance(Light light)
+{
+  if (light.kind == LIGHT_DIRECTIONAL)
+  {
+    return vec3(light.intensity / max(EPSILON, directionalSolidAngle(light)));
+  }
+  return vec3(light.intensity);
+}
+
+/**
+ * Solid angle pdf of sampling `dir` on a non-delta light.
+ *
+ * @param dir Direction from the shaded point to the light
+ * @param dist Distance between the shaded point and the light sample
+ */
+float
+lightPdf(Light light, vec3 dir, float dist)
+{
+  if (light.kind == LIGHT_DIRECTIONAL)
+  {
+    return 1.0 / max(EPSILON, directionalSolidAngle(light));
+  }
+  float cosLight = dot(normalize(light.normal.xyz), - dir);
+  float area = length(cross(light.tangent.xyz, light.bitangent.xyz));
+  return (dist * dist) / max(EPSILON, cosLight * area);
+}
+
+LightSample
+sampleRectLight(Light light, vec3 position, inout uint seed)
+{
+  vec3 point = lightOrigin(light) + rand(seed) * light.tangent.xyz + rand(seed) * light.bitangent.xyz;
+  vec3 toLight = point - position;
+
+  LightSample lightSample;
+  lightSample.dist = length(toLight);
+  lightSample.dir = toLight / max(EPSILON, lightSample.dist);
+  lightSample.delta = false;
+
+  float cosLight = dot(normalize(light.normal.xyz), - lightSample.dir);
+  lightSample.radiance = cosLight > 0.0 ? lightRadiance(light) : vec3(0.0);
+  lightSample.pdf = lightPdf(light, lightSample.dir, lightSample.dist);
+  return lightSample;
+}
+
+/**
+ * Uniformly samples the cone of directions subtended by the light.
+ */
+LightSample
+sampleDirectionalLight(Light light, inout uint seed)
+{
+  LightSample lightSample;
+  lightSample.dist = MAX_FLOAT;
+  lightSample.delta = isDeltaLight(light);
+
+  vec3 axis = - normalize(light.normal.xyz);
+  if (lightSample.delta)
+  {
+    lightSample.dir = axis;
+    lightSample.radiance = vec3(light.intensity);
+    lightSample.pdf = 1.0;
+    return lightSample;
+  }
+
+  float cosTheta = mix(1.0, light.cosOuter, rand(seed));
+  float sinTheta = sqrt(max(0.0, 1.0 - cosTheta * cosTheta));
+  float phi = rand(seed) * TWO_PI;
+
+  vec3 worldUp = abs(axis.z) < 0.9999 ? vec3(0, 0, 1) : vec3(1, 0, 0);
+  vec3 tangent = normalize(cross(worldUp, axis));
+  vec3 bitangent = cross(axis, tangent);
+  vec3 local = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+  lightSample.dir = normalize(project(local, axis, tangent, bitangent));
+  lightSample.radiance = lightRadiance(light);
+  lightSample.pdf = lightPdf(light, lightSample.dir, lightSample.dist);
+  return lightSample;
+}
+
+/**
+ * Samples point and spot lights, using an inverse square falloff.
+ *
+ * Spot lights are smoothly attenuated between the inner and outer cones.
+ */
+LightSample
+samplePunctualLight(Light light, vec3 position)
+{
+  vec3 toLight = lightOrigin(light) - position;
+
+  LightSample lightSample;
+  lightSample.dist = length(toLight);
+  lightSample.dir = toLight / max(EPSILON, lightSample.dist);
+  lightSample.pdf = 1.0;
+  lightSample.delta = true;
+
+  float attenuation = 1.0 / max(EPSILON, lightSample.dist * lightSample.dist);
+  if (light.kind == LIGHT_SPOT)
+  {
+    float cosAngle = dot(normalize(light.normal.xyz), - lightSample.dir);
+    attenuation *= smoothstep(light.cosOuter, light.cosInner, cosAngle);
+  }
+  lightSample.radiance = vec3(light.intensity * attenuation);
+  return lightSample;
+}
+
+/**
+ * Samples a direction towards the light, as seen from `position`.
+ *
+ * `radiance` is zero if the sample doesn't reach `position`, e.g., when
+ * sampling the back of a rectangle.
+ */
+LightSample
+sampleLight(Light light, vec3 position, inout uint seed)
+{
+  if (light.kind == LIGHT_DIRECTIONAL)
+  {
+    return sampleDirectionalLight(light, seed);
+  }
+  if (light.kind == LIGHT_POINT || light.kind == LIGHT_SPOT)
+  {
+    return samplePunctualLight(light, position);
+  }
+  return sampleRectLight(light, position, seed);
+}
+
+/**
+ * Intersects the emitting side of non-delta lights.
+ *
+ * Directional lights are infinitely far, and are hit just before `MAX_FLOAT`.
+ *
+ * @return Distance to the light, or MAX_FLOAT if missed
+ */
+float
+intersectLight(Light light, Ray ray)
+{
+  if (isDeltaLight(light))
+  {
+    return MAX_FLOAT;
+  }
+  if (light.kind == LIGHT_DIRECTIONAL)
+  {
+    float cosAngle = dot(- normalize(light.normal.xyz), ray.dir);
+    return cosAngle >= light.cosOuter ? MAX_FLOAT * 0.5 : MAX_FLOAT;
+  }
+  return intersectPlane(
+    ray,
+    - normalize(light.normal.xyz),
+    lightOrigin(light),
+    light.tangent.xyz,
+    light.bitangent.xyz
+  );
+}
+
+#endif // LIGHTS_H
@@ -23,3 +23,39 @@ rand(inout uint seed)
 {
   return float(WangHash(seed)) / 4294967296.0;
 }
+
+#define JITTER_NONE 0u
+#define JITTER_HALTON 1u
+#define JITTER_R2 2u
+
+float
+radicalInverse(uint index, uint base)
+{
+  float f = 1.0;
+  float result = 0.0;
+  while (index > 0u)
+  {
+    f /= float(base);
+    result += f * float(index % base);
+    index /= base;
+  }
+  return result;
+}
+
+// Sub-pixel offset of the camera rays for `frame`, in `[-0.5; 0.5[`.
+// Must match `Jitter::offset`.
+vec2
+subpixelJitter(uint jitter, uint frame)
+{
+  if (jitter == JITTER_HALTON)
+  {
+    uint index = frame % 16u + 1u;
+    return vec2(radicalInverse(index, 2u), radicalInverse(index, 3u)) - vec2(0.5);
+  }
+  if (jitter == JITTER_R2)
+  {
+    uvec2 fixedPoint = uvec2(frame) * uvec2(0xC13FA9A9u, 0x91E10DA5u) + uvec2(0x80000000u);
+    return vec2(fixedPoint) / 4294967296.0 - vec2(0.5);
+  }
+  return vec2(0.0);
+}
@@ -93,3 +93,22 @@ unpackGbuffer(uvec4 data)
   s.id = data.a;
   return s;
 }
+
+/**
+ * Fraction of each channel routed to the specular signal, the remaining
+ * going to the diffuse one.
+ *
+ * Quantized to 8 bits per channel, and stored as an integer exactly
+ * representable by a float.
+ */
+float
+packSignalSplit(vec3 specularFraction)
+{
+  return float(packUnorm4x8(vec4(specularFraction, 0.0)));
+}
+
+vec3
+unpackSignalSplit(float split)
+{
+  return unpackUnorm4x8(uint(split)).rgb;
+}
@@ -11,11 +11,14 @@
 struct BSDFSample
 {
 	vec3 dir;
+  // Surface to eye direction.
+  vec3 w0;
   vec3 H;
   float NdotL;
   float NdotH;
   float LdotH;
   float NdotV;
+  float VdotH;
   float pdf;
 };
 
@@ -32,6 +35,27 @@ struct MaterialState
   float perceptualRoughness;
   float roughness;
   float roughness2;
+  float transmission;
+  // Ratio of the index of refraction on the view side over the other side.
+  float eta;
+  float clearcoat;
+  float clearcoatRoughness2;
+  vec3 sheenColor;
+  float sheenRoughness;
+  // Direction of the anisotropy, orthogonal to the normal.
+  vec3 anisotropyDirection;
+  float anisotropy;
+};
+
+/**
+ * Probability to sample each lobe.
+ */
+struct LobeRatios
+{
+  float diffuse;
+  float specular;
+  float clearcoat;
+  float transmission;
 };
 
 /*
@@ -76,6 +100,52 @@ float GeometrySmith_GGX(float NdotV, float a2)
 	return 1.0 / (NdotV + sqrt(a2 + b - a2 * b));
 }
 
+/**
+ * Anisotropic version of `GTR2`, Burley.
+ *
+ * @param HdotX Projection of the half vector on the anisotropy direction
+ * @param HdotY Projection of the half vector on the orthogonal direction
+ * @param ax Roughness along the anisotropy direction
+ * @param ay Roughness along the orthogonal direction
+ */
+float GTR2_aniso(float NdotH, float HdotX, float HdotY, float ax, float ay)
+{
+  float x = HdotX / ax;
+  float y = HdotY / ay;
+  float t = x * x + y * y + NdotH * NdotH;
+  return 1.0 / max(EPSILON, PI_F * ax * ay * t * t);
+}
+
+/**
+ * Anisotropic version of `GeometrySmith_GGX`.
+ */
+float GeometrySmith_GGX_aniso(float NdotV, float VdotX, float VdotY, float ax, float ay)
+{
+  float x = VdotX * ax;
+  float y = VdotY * ay;
+  return 1.0 / (NdotV + sqrt(x * x + y * y + NdotV * NdotV));
+}
+
+/**
+ * Sheen distribution: "Charlie", Estevez and Kulla.
+ *
+ * @param alpha The sheen roughness squared
+ */
+float Charlie_Sheen(float NdotH, float alpha)
+{
+  float invAlpha = 1.0 / alpha;
+  float sin2h = max(1.0 - NdotH * NdotH, 0.0078125);
+  return (2.0 + invAlpha) * pow(sin2h, invAlpha * 0.5) / (2.0 * PI_F);
+}
+
+/**
+ * Sheen visibility term, Neubelt and Pettineo.
+ */
+float Visibility_Sheen(float NdotL, float NdotV)
+{
+  return 1.0 / (4.0 * (NdotL + NdotV - NdotL * NdotV));
+}
+
 /**
  * Generate a random sample based on the Lambert diffuse BRDF.
  *
@@ -111,19 +181,17 @@ vec3 randomSampleDiffuse_Lambert(const vec3 normal, inout uint seed)
 }
 
 /**
- * Generate a random sample based on the GGX specular BRDF.
+ * Generate a random microfacet normal based on the GGX distribution.
  *
- * @param w0Surface to eye direction vector
  * @param normal The normal to the evaluated surface
  * @param tangent The tangent to the evaluated surface
  * @param bitangent The bitangent to the evaluated surface
  * @param roughness2 The roughness squared
  * @param seed The current value of a seed variable
  *
- * @return A random direction generated based on the GGX specular BRDF
+ * @return A random half vector, in the hemisphere of `normal`
  */
-vec3 randomSampleSpecular_GGX(
-  const vec3 w0,
+vec3 randomSampleHalfVector_GGX(
   const vec3 normal,
   const vec3 tangent,
   const vec3 bitangent,
@@ -142,7 +210,62 @@ vec3 randomSampleSpecular_GGX(
   float cosPhi = cos(phi);
 
   vec3 H = vec3(sinTheta * cosPhi, sinTheta * sinPhi, cosTheta);
-  H = project(H, normal, tangent, bitangent);
+  return project(H, normal, tangent, bitangent);
+}
+
+/**
+ * Generate a random microfacet normal based on the anisotropic GGX distribution.
+ *
+ * @param normal The normal to the evaluated surface
+ * @param X The anisotropy direction
+ * @param Y The direction orthogonal to `normal` and `X`
+ * @param ax Roughness along `X`
+ * @param ay Roughness along `Y`
+ * @param seed The current value of a seed variable
+ *
+ * @return A random half vector, in the hemisphere of `normal`
+ */
+vec3 randomSampleHalfVector_GGX_aniso(
+  const vec3 normal,
+  const vec3 X,
+  const vec3 Y,
+  const float ax,
+  const float ay,
+  inout uint seed
+)
+{
+  float r1 = rand(seed);
+  float r2 = rand(seed);
+
+  float phi = r1 * 2.0 * PI_F;
+  // Stretched isotropic sample.
+  float tanTheta = sqrt(r2 / max(EPSILON, 1.0 - r2));
+  vec3 H = vec3(ax * tanTheta * cos(phi), ay * tanTheta * sin(phi), 1.0);
+  return normalize(project(normalize(H), normal, X, Y));
+}
+
+/**
+ * Generate a random sample based on the GGX specular BRDF.
+ *
+ * @param w0Surface to eye direction vector
+ * @param normal The normal to the evaluated surface
+ * @param tangent The tangent to the evaluated surface
+ * @param bitangent The bitangent to the evaluated surface
+ * @param roughness2 The roughness squared
+ * @param seed The current value of a seed variable
+ *
+ * @return A random direction generated based on the GGX specular BRDF
+ */
+vec3 randomSampleSpecular_GGX(
+  const vec3 w0,
+  const vec3 normal,
+  const vec3 tangent,
+  const vec3 bitangent,
+  const float roughness2,
+  inout uint seed
+)
+{
+  vec3 H = randomSampleHalfVector_GGX(normal, tangent, bitangent, roughness2, seed);
   return 2.0 * dot(w0, H) * H - w0;
 }
 
@@ -158,92 +281,326 @@ SchlickFresnel(float u)
 }
 
 /**
- * Samples the BSDF function based on geometry and material data.
+ * Directional albedo of the specular lobe, used to demodulate the specular signal.
+ *
+ * Based on: Physically Based Shading on Mobile, Karis 2014
+ */
+vec3
+specularReflectance(vec3 f0, float perceptualRoughness, float NdotV)
+{
+  const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+  const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+  vec4 r = perceptualRoughness * c0 + c1;
+  float a004 = min(r.x * r.x, exp2(-9.28 * NdotV)) * r.x + r.y;
+  vec2 AB = vec2(-1.04, 1.04) * a004 + r.zw;
+  return f0 * AB.x + AB.y;
+}
+
+/**
+ * Exact fresnel reflectance of a dielectric interface, for unpolarized light.
+ *
+ * @param cosThetaI Cosine of the angle between the incident direction and the normal
+ * @param eta Ratio of the index of refraction on the incident side over the other side
+ */
+float
+dielectricFresnel(float cosThetaI, float eta)
+{
+  float sinThetaT2 = eta * eta * (1.0 - cosThetaI * cosThetaI);
+  // Total internal reflection.
+  if (sinThetaT2 >= 1.0) { return 1.0; }
+
+  float cosThetaT = sqrt(1.0 - sinThetaT2);
+  float rs = (eta * cosThetaI - cosThetaT) / (eta * cosThetaI + cosThetaT);
+  float rp = (cosThetaI - eta * cosThetaT) / (cosThetaI + eta * cosThetaT);
+  return 0.5 * (rs * rs + rp * rp);
+}
+
+/**
+ * Weight of the dielectric transmission lobe.
+ */
+float
+transmissionWeight(const MaterialState mat)
+{
+  return (1.0 - mat.metallic) * mat.transmission;
+}
+
+/**
+ * Roughness along the anisotropy direction and along the orthogonal direction.
+ *
+ * Follows `KHR_materials_anisotropy`.
+ */
+vec2
+anisotropicRoughness(const MaterialState mat)
+{
+  return vec2(mix(mat.roughness, 1.0, mat.anisotropy * mat.anisotropy), mat.roughness);
+}
+
+LobeRatios
+lobeRatios(const MaterialState mat)
+{
+  float transmission = transmissionWeight(mat);
+  float sheen = max(mat.sheenColor.r, max(mat.sheenColor.g, mat.sheenColor.b));
+
+  LobeRatios ratios;
+  ratios.clearcoat = 0.25 * mat.clearcoat;
+  float base = 1.0 - ratios.clearcoat;
+  ratios.transmission = base * transmission;
+  // Sheen is broad, and sampled with the diffuse lobe.
+  ratios.diffuse = base * 0.5 * max(1.0 - mat.metallic, sheen) * (1.0 - transmission);
+  ratios.specular = base - ratios.transmission - ratios.diffuse;
+  return ratios;
+}
+
+/**
+ * Multiple importance sampling weight, using the power heuristic.
+ *
+ * @param pdf Pdf of the strategy used to generate the sample
+ * @param otherPdf Pdf of the other strategy, for the same sample
+ */
+float
+powerHeuristic(float pdf, float otherPdf)
+{
+  float pdf2 = pdf * pdf;
+  return pdf2 / max(EPSILON, pdf2 + otherPdf * otherPdf);
+}
+
+/**
+ * Computes the BSDF sample data for a given direction.
+ *
+ * Used to evaluate directions not generated by the BSDF, e.g., light samples.
  *
  * @param w0 Surface to eye direction vector
  * @param normal The normal to the evaluated surface
  * @param mat The material data
- * @param seed The current value of a seed variable
+ * @param L Surface to light direction vector
  */
 BSDFSample
-sampleBSDF_UE4(
+directionSample_UE4(
   const vec3 w0,
   const vec3 normal,
   const MaterialState mat,
-  inout uint seed
+  const vec3 L
 )
 {
   BSDFSample bsdf;
 
-  vec3 worldUp = abs(normal.z) < 0.9999 ? vec3(0, 0, 1) : vec3(1, 0, 0);
-  vec3 tangent = normalize(cross(worldUp, normal));
-  vec3 bitangent = cross(normal, tangent);
-
-  float diffuseRatio = 0.5 * (1.0 - mat.metallic);
-  float specularRatio = 1.0 - diffuseRatio;
+  LobeRatios ratios = lobeRatios(mat);
 
-  /*
-   * 1. Sample BSDF direction
-   */
+  bsdf.dir = L;
+  bsdf.w0 = w0;
+  bsdf.NdotL = dot(normal, L);
+	bsdf.NdotV = dot(normal, w0);
 
-  float probability = rand(seed);
-  if (probability < diffuseRatio)
+  bool refracted = ratios.transmission > 0.0 && bsdf.NdotL < 0.0;
+  if (refracted)
   {
-    bsdf.dir = randomSampleDiffuse_Lambert(normal, tangent, bitangent, seed);
+    // Generalized half vector, oriented towards the normal.
+    bsdf.H = - normalize(mat.eta * w0 + L);
+    bsdf.H = dot(bsdf.H, normal) < 0.0 ? - bsdf.H : bsdf.H;
   }
   else
   {
-    bsdf.dir = randomSampleSpecular_GGX(w0, normal, tangent, bitangent, mat.roughness2, seed);
+    bsdf.H = normalize(L + w0);
   }
-
-  /*
-   * 1. Sample PDF
-   */
-
-  vec3 L = bsdf.dir;
-  bsdf.H = normalize(L + w0);
-  bsdf.NdotL = dot(normal, L);
   bsdf.NdotH = dot(normal, bsdf.H);
 	bsdf.LdotH = dot(L, bsdf.H);
-	bsdf.NdotV = dot(normal, w0);
+	bsdf.VdotH = dot(w0, bsdf.H);
 
   float cosTheta = abs(bsdf.NdotH);
   float pdfGTR2 = GTR2(cosTheta, mat.roughness2) * cosTheta;
 
+  float pdfDielectric = 0.0;
+  if (ratios.transmission > 0.0)
+  {
+    float F = dielectricFresnel(abs(bsdf.VdotH), mat.eta);
+    float denom = mat.eta * bsdf.VdotH + bsdf.LdotH;
+    pdfDielectric = refracted
+      ? (1.0 - F) * pdfGTR2 * abs(bsdf.LdotH) / (denom * denom + EPSILON)
+      : F * pdfGTR2 / (4.0 * abs(bsdf.LdotH) + EPSILON);
+  }
+  if (refracted)
+  {
+    bsdf.pdf = ratios.transmission * pdfDielectric;
+    return bsdf;
+  }
+
+  vec3 X = mat.anisotropyDirection;
+  vec3 Y = cross(normal, X);
+  vec2 roughness = anisotropicRoughness(mat);
+  float pdfAniso = GTR2_aniso(cosTheta, dot(bsdf.H, X), dot(bsdf.H, Y), roughness.x, roughness.y) * cosTheta;
+  float pdfCoat = GTR2(cosTheta, mat.clearcoatRoughness2) * cosTheta;
+
   // Calculate diffuse and specular pdfs and mix ratio
-  float pdfSpec = pdfGTR2 / (4.0 * abs(bsdf.LdotH) + EPSILON);
+  float pdfSpec = pdfAniso / (4.0 * abs(bsdf.LdotH) + EPSILON);
+  float pdfClearcoat = pdfCoat / (4.0 * abs(bsdf.LdotH) + EPSILON);
   float pdfDiff = abs(bsdf.NdotL) * (1.0 / PI_F);
 
   // Weight pdfs according to ratios
-  bsdf.pdf = diffuseRatio * pdfDiff + specularRatio * pdfSpec;
+  bsdf.pdf = ratios.diffuse * pdfDiff
+    + ratios.specular * pdfSpec
+    + ratios.clearcoat * pdfClearcoat
+    + ratios.transmission * pdfDielectric;
   return bsdf;
 }
 
 /**
- * Evaluates a sample with the given BSDF and geometric data.
+ * Samples the BSDF function based on geometry and material data.
+ *
+ * @param w0 Surface to eye direction vector
+ * @param normal The normal to the evaluated surface
+ * @param mat The material data
+ * @param seed The current value of a seed variable
+ */
+BSDFSample
+sampleBSDF_UE4(
+  const vec3 w0,
+  const vec3 normal,
+  const MaterialState mat,
+  inout uint seed
+)
+{
+  vec3 worldUp = abs(normal.z) < 0.9999 ? vec3(0, 0, 1) : vec3(1, 0, 0);
+  vec3 tangent = normalize(cross(worldUp, normal));
+  vec3 bitangent = cross(normal, tangent);
+
+  LobeRatios ratios = lobeRatios(mat);
+
+  vec3 dir;
+  float probability = rand(seed);
+  if (probability < ratios.diffuse)
+  {
+    dir = randomSampleDiffuse_Lambert(normal, tangent, bitangent, seed);
+  }
+  else if (probability < ratios.diffuse + ratios.specular)
+  {
+    vec2 roughness = anisotropicRoughness(mat);
+    vec3 X = mat.anisotropyDirection;
+    vec3 H = randomSampleHalfVector_GGX_aniso(normal, X, cross(normal, X), roughness.x, roughness.y, seed);
+    dir = reflect(- w0, H);
+  }
+  else if (probability < 1.0 - ratios.transmission)
+  {
+    dir = randomSampleSpecular_GGX(w0, normal, tangent, bitangent, mat.clearcoatRoughness2, seed);
+  }
+  else
+  {
+    // Dielectric lobe, reflects or refracts based on the fresnel term.
+    vec3 H = randomSampleHalfVector_GGX(normal, tangent, bitangent, mat.roughness2, seed);
+    float F = dielectricFresnel(abs(dot(w0, H)), mat.eta);
+    dir = rand(seed) < F ? reflect(- w0, H) : refract(- w0, H, mat.eta);
+  }
+  return directionSample_UE4(w0, normal, mat, dir);
+}
+
+/**
+ * Evaluates the rough dielectric BSDF, reflection and transmission.
+ *
+ * Refraction is tinted by the albedo.
+ *
+ * Based on: Microfacet Models for Refraction through Rough Surfaces, Walter et al. 2007
+ */
+vec3 evalDielectric_GGX(const BSDFSample bsdf, const MaterialState mat)
+{
+  float NdotL = abs(bsdf.NdotL);
+  if (NdotL <= EPSILON) { return vec3(0.0); }
+
+  float D = GTR2(abs(bsdf.NdotH), mat.roughness2);
+  float F = dielectricFresnel(abs(bsdf.VdotH), mat.eta);
+  // Visibility terms, i.e., `G1 / (2 * NdotX)`.
+  float G = GeometrySmith_GGX(bsdf.NdotV, mat.roughness2) * GeometrySmith_GGX(NdotL, mat.roughness2);
+  if (bsdf.NdotL > 0.0)
+  {
+    return vec3(F * D * G);
+  }
+  float denom = mat.eta * bsdf.VdotH + bsdf.LdotH;
+  float transmitted = abs(bsdf.VdotH * bsdf.LdotH) * 4.0 * G * D / (denom * denom + EPSILON);
+  return mat.albedo * (1.0 - F) * transmitted;
+}
+
+/**
+ * Evaluates a sample with the given BSDF and geometric data, per lobe.
  * This method is based on a general Cook-Torrance model.
  *
  * @param bsdf The BSDF sample to evaluate
  * @param normal The normal to the evaluated surface
  * @param mat The material data
+ * @param diffuse Diffuse and sheen lobes
+ * @param specular Specular, transmission, and clearcoat lobes
  *
  * This method accepts only PBR materials based on the metal-roughness
- * workflow.
+ * workflow. Sheen and clearcoat are layered on top of the base material,
+ * following `KHR_materials_sheen` and `KHR_materials_clearcoat`.
  *
  * This method is inspired and modified from:
  *  - OpenGLPathtracer: https://github.com/RobertBeckebans/OpenGL-PathTracer/blob/master/PathTracer/src/shaders/Progressive/PathTraceFrag.glsl
  *  - Real shading in Unreal Engine 4: https://blog.selfshadow.com/publications/s2013-shading-course/karis/s2013_pbs_epic_notes_v2.pdf
  */
-vec3 evalSample_UE4(const BSDFSample bsdf, const vec3 normal, const MaterialState mat)
+void evalLobes_UE4(
+  const BSDFSample bsdf,
+  const vec3 normal,
+  const MaterialState mat,
+  out vec3 diffuse,
+  out vec3 specular
+)
 {
-	if (bsdf.NdotL <= EPSILON || bsdf.NdotV <= EPSILON) { return vec3(0.0); }
+  diffuse = vec3(0.0);
+  specular = vec3(0.0);
+	if (bsdf.NdotV <= EPSILON) { return; }
+
+  float transmissionRatio = transmissionWeight(mat);
+  if (bsdf.NdotL > EPSILON)
+  {
+    vec3 X = mat.anisotropyDirection;
+    vec3 Y = cross(normal, X);
+    vec2 roughness = anisotropicRoughness(mat);
+
+    float Ds = GTR2_aniso(bsdf.NdotH, dot(bsdf.H, X), dot(bsdf.H, Y), roughness.x, roughness.y);
+    float FH = SchlickFresnel(bsdf.LdotH);
+    float Gs = GeometrySmith_GGX_aniso(bsdf.NdotL, dot(bsdf.dir, X), dot(bsdf.dir, Y), roughness.x, roughness.y)
+      * GeometrySmith_GGX_aniso(bsdf.NdotV, dot(bsdf.w0, X), dot(bsdf.w0, Y), roughness.x, roughness.y);
+    vec3 Fs = mix(mat.f0, vec3(1.0), FH);
+    diffuse = (mat.albedo / PI_F) * (1.0 - mat.metallic) * (1.0 - transmissionRatio);
+    specular = Gs * Fs * Ds * (1.0 - transmissionRatio);
+  }
+  if (transmissionRatio > 0.0)
+  {
+    specular += evalDielectric_GGX(bsdf, mat) * transmissionRatio;
+  }
+
+  // @todo: scale the base by the sheen directional albedo, requires a LUT.
+  if (bsdf.NdotL > EPSILON && any(greaterThan(mat.sheenColor, vec3(0.0))))
+  {
+    float alpha = max(1e-3, mat.sheenRoughness * mat.sheenRoughness);
+    float sheen = Charlie_Sheen(bsdf.NdotH, alpha) * Visibility_Sheen(bsdf.NdotL, bsdf.NdotV);
+    diffuse += mat.sheenColor * sheen;
+  }
+
+  if (mat.clearcoat > 0.0)
+  {
+    // Coat with an index of refraction of `1.5`.
+    float Fc = mat.clearcoat * (0.04 + 0.96 * SchlickFresnel(bsdf.NdotV));
+    diffuse *= 1.0 - Fc;
+    specular *= 1.0 - Fc;
+    if (bsdf.NdotL > EPSILON)
+    {
+      float Dc = GTR2(bsdf.NdotH, mat.clearcoatRoughness2);
+      float Gc = GeometrySmith_GGX(bsdf.NdotL, mat.clearcoatRoughness2) * GeometrySmith_GGX(bsdf.NdotV, mat.clearcoatRoughness2);
+      specular += vec3(Fc * Dc * Gc);
+    }
+  }
+}
 
-	float Ds = GTR2(bsdf.NdotH, mat.roughness2);
-	float FH = SchlickFresnel(bsdf.LdotH);
-	float Gs = GeometrySmith_GGX(bsdf.NdotL, mat.roughness2) * GeometrySmith_GGX(bsdf.NdotV, mat.roughness2);
-	vec3 Fs = mix(mat.f0, vec3(1.0), FH);
-  vec3 diffuse = (mat.albedo / PI_F) * (1.0 - mat.metallic);
-	return diffuse + Gs * Fs * Ds;
+/**
+ * Evaluates a sample with the given BSDF and geometric data.
+ *
+ * See `evalLobes_UE4`.
+ */
+vec3 evalSample_UE4(const BSDFSample bsdf, const vec3 normal, const MaterialState mat)
+{
+  vec3 diffuse;
+  vec3 specular;
+  evalLobes_UE4(bsdf, normal, mat, diffuse, specular);
+  return diffuse + specular;
 }
 
 #endif // SAMPLING_H
@@ -5,9 +5,13 @@ struct GlobalUniforms
 {
   uint frame;
   uint seed;
+  // `0` for no limit.
   uint bounces;
-  uint padding;
+  uint minBounces;
   uvec2 dimensions;
+  // Sub-pixel jitter sequence, see `JITTER_*`.
+  uint jitter;
+  uint padding;
 };
 
 struct BVHNode {
@@ -18,47 +22,157 @@ struct BVHNode {
   vec4 n4;
 };
 
+struct TLASNode {
+  vec3 min;
+  // Left child index for inner nodes, instance index for leaves.
+  uint leftFirst;
+  vec3 max;
+  uint instanceCount;
+};
+
+// Must match `VisibilityMask` in `uniforms.rs`.
+#define VISIBILITY_CAMERA 0x1u
+#define VISIBILITY_INDIRECT 0x2u
+#define VISIBILITY_SHADOW 0x4u
+#define VISIBILITY_PICKING 0x8u
+#define VISIBILITY_ALL 0xFFu
+
+// Must match `InstanceFlags` in `uniforms.rs`.
+#define INSTANCE_OPAQUE 0x1u
+#define INSTANCE_PROCEDURAL 0x2u
+
+// Must match `LightKind` in `uniforms.rs`.
+#define LIGHT_RECT 0u
+#define LIGHT_DIRECTIONAL 1u
+#define LIGHT_POINT 2u
+#define LIGHT_SPOT 3u
+
+// Must match `ProceduralKind` in `uniforms.rs`.
+#define PROCEDURAL_SPHERE 0u
+#define PROCEDURAL_BOX 1u
+
 struct Instance
 {
   // @todo: reduce size of this struct.
   mat4 modelToWorld;
   mat4 worldToModel;
+  // Transform of the previous frame, for motion vectors.
+  mat4 previousModelToWorld;
   uint materialIndex;
   uint bvhRootIndex;
   uint vertexRootIndex;
   uint primitiveRootIndex;
+  uint mask;
+  uint flags;
+  uint entryIndex;
+  uint padding_0;
 };
 
+/**
+ * Analytic primitive, bounded by `min` and `max`.
+ *
+ * Spheres are inscribed in the bounds.
+ */
+struct ProceduralPrimitive
+{
+  vec3 min;
+  uint kind;
+  vec3 max;
+  uint originalPrimitive;
+};
+
+struct Material
+{
+  vec4  color;
+  float roughnessFactor;
+  float metallic;
+  uint  albedoTexture;
+  // @todo: for now, metal in B channel and roughness in G.
+  uint  mraTexture;
+  // Hits with an alpha below the cutoff are ignored, `0.0` for opaque materials.
+  float alphaCutoff;
+  // Tangent space normal map.
+  uint  normalTexture;
+  uint  emissiveTexture;
+  float transmission;
+  vec3  emissive;
+  float emissiveStrength;
+  // Absorption coefficient of the enclosed volume.
+  vec3  attenuation;
+  float ior;
+  float clearcoat;
+  float clearcoatRoughness;
+  float sheenRoughness;
+  // Strength of the anisotropy, in `[0; 1]`.
+  float anisotropyStrength;
+  vec3  sheenColor;
+  // Rotation of the anisotropy direction from the tangent, in radians.
+  float anisotropyRotation;
+};
+
+/**
+ * - `uv` saved in `position.w` and `normal.w`
+ * - `tangent.w` holds the handedness of the bitangent, `tangent` is zero if unavailable
+ */
 struct Vertex
 {
   vec4 position;
   vec4 normal;
+  vec4 tangent;
 };
 
+/**
+ * - Position saved in `normal.w`, `tangent.w`, and `bitangent.w`
+ * - `cosInner` and `cosOuter` are the cone angles of spot lights, `cosOuter`
+ *   is the angular radius of directional lights
+ */
 struct Light
 {
   vec4 normal;
   vec4 tangent;
   vec4 bitangent;
   float intensity;
-  float padding_0;
-  float padding_1;
-  float padding_2;
+  uint kind;
+  float cosInner;
+  float cosOuter;
 };
 
 /**
  * - `throughput` saved in `origin.w`, `dir.w`, `radiance,w`
+ * - `terminated.z` holds the visibility mask of the ray
+ * - `terminated.w` holds the float bits of the BSDF pdf of the last bounce, `0` for camera rays
+ * - `specular.rgb` holds the specular signal of the denoiser, `radiance.rgb` the diffuse one,
+ *   and `specular.w` the split of the radiance gathered after the primary hit,
+ *   see `packSignalSplit`
  */
 struct RayPayload {
   vec4 origin;
   vec4 dir;
   vec4 radiance;
   uvec4 terminated;
+  vec4 specular;
+};
+
+/**
+ * Shadow ray, traced towards a light sample.
+ *
+ * - `origin.w` holds the distance to the light
+ * - `radiance` is added to the path if the light is visible, zero if unused,
+ *   with the split of the diffuse and specular signals in `w`, see `packSignalSplit`
+ * - `bounceRadiance` is the radiance gathered by the shading pass, with the
+ *   bounce in `w`, negative if unused. Only written with `AOV_BOUNCE_RADIANCE`
+ */
+struct ShadowRay {
+  vec4 origin;
+  vec4 dir;
+  vec4 radiance;
+  vec4 bounceRadiance;
 };
 
 struct Ray {
   vec3 origin;
   vec3 dir;
+  uint mask;
 };
 
 struct Intersection {
@@ -71,4 +185,18 @@ struct Intersection {
   float padding_1; // not needed I think
 };
 
+/**
+ * Must match `SvgfParameters` in `uniforms.rs`.
+ */
+struct SvgfParameters {
+  float phiDepth;
+  float phiNormal;
+  float phiLuminance;
+  float depthThreshold;
+  float normalThreshold;
+  uint maxHistory;
+  uint varianceHistory;
+  uint padding;
+};
+
 #endif // STRUCTS_H
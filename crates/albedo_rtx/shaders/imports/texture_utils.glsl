@@ -28,4 +28,20 @@ fetchTexture(uint textureIndex, vec2 uv)
   );
 }
 
+/**
+ * Perturb `normal` using a tangent space normal map.
+ *
+ * @param normal Normalized shading normal, in world space
+ * @param tangent Tangent in world space, with the bitangent handedness in `w`
+ */
+vec3
+sampleNormalMap(uint textureIndex, vec2 uv, vec3 normal, vec4 tangent)
+{
+  // Gram-Schmidt, interpolated tangents aren't orthogonal to the normal anymore.
+  vec3 t = normalize(tangent.xyz - normal * dot(normal, tangent.xyz));
+  vec3 b = cross(normal, t) * tangent.w;
+  vec3 mapped = fetchTexture(textureIndex, uv).xyz * 2.0 - 1.0;
+  return normalize(mapped.x * t + mapped.y * b + mapped.z * normal);
+}
+
 #endif
@@ -31,6 +31,14 @@ layout (set = 0, binding = 4) uniform GlobalUniformBuffer {
   GlobalUniforms global;
 };
 
+layout (set = 0, binding = 5, std430) readonly buffer TLASNodeBuffer {
+  TLASNode tlasNodes[];
+};
+
+layout (set = 0, binding = 6, std430) readonly buffer ProceduralBuffer {
+  ProceduralPrimitive procedurals[];
+};
+
 #include "imports/common.glsl"
 #include "imports/intersection_utils.glsl"
 #include "imports/sampling.glsl"
@@ -56,6 +64,7 @@ void main() {
     Ray ray;
     ray.origin = vPositionWorld;
     ray.dir = rayDir;
+    ray.mask = VISIBILITY_SHADOW;
 
     Intersection intersection = sceneHit(ray);
     if(intersection.dist >= radius)
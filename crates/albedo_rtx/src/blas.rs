@@ -1,6 +1,12 @@
-use crate::{uniforms::Instance, BVHNode, BVHPrimitive, Vertex};
+use crate::{
+    generate_tangents,
+    uniforms::{Instance, InstanceFlags},
+    BVHNode, BVHPrimitive, ProceduralPrimitive, Vertex,
+};
+use albedo_math::AABB;
+use std::collections::BTreeSet;
+use std::ops::Range;
 
-#[cfg(not(feature = "tinybvh"))]
 use obvhs::{self, triangle::Triangle};
 #[cfg(feature = "tinybvh")]
 use tinybvh_rs::cwbvh;
@@ -10,6 +16,10 @@ pub struct MeshDescriptor<'a> {
     pub positions: pas::Slice<'a, [f32; 4]>,
     pub normals: Option<pas::Slice<'a, [f32; 3]>>,
     pub texcoords0: Option<pas::Slice<'a, [f32; 2]>>,
+    /// Tangents, with the bitangent handedness in `w`.
+    ///
+    /// Generated from the normals and texture coordinates when missing.
+    pub tangents: Option<pas::Slice<'a, [f32; 4]>>,
 }
 
 #[derive(Copy, Clone)]
@@ -18,6 +28,142 @@ pub struct IndexedMeshDescriptor<'a> {
     pub indices: &'a [u32],
 }
 
+/// Library used to build the BVH nodes.
+///
+/// Builders produce different trees, the builder of each entry is
+/// recorded in [`BLASEntryDescriptor::builder`].
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BLASBuilder {
+    #[cfg(feature = "tinybvh")]
+    TinyBVH = 1,
+    Obvhs = 2,
+}
+
+impl BLASBuilder {
+    /// Default builder, selected at compile time via the `tinybvh` feature.
+    pub const fn current() -> Self {
+        #[cfg(feature = "tinybvh")]
+        {
+            Self::TinyBVH
+        }
+        #[cfg(not(feature = "tinybvh"))]
+        {
+            Self::Obvhs
+        }
+    }
+
+    /// Whether the builder implements the `quality` preset.
+    pub fn supports(&self, quality: BlasBuildQuality) -> bool {
+        match self {
+            #[cfg(feature = "tinybvh")]
+            Self::TinyBVH => matches!(
+                quality,
+                BlasBuildQuality::Medium | BlasBuildQuality::SpatialSplits
+            ),
+            Self::Obvhs => true,
+        }
+    }
+
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            #[cfg(feature = "tinybvh")]
+            1 => Some(Self::TinyBVH),
+            2 => Some(Self::Obvhs),
+            _ => None,
+        }
+    }
+}
+
+impl Default for BLASBuilder {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+/// Quality preset of the BVH build.
+///
+/// Higher quality trees are faster to traverse, but slower to build.
+///
+/// Note: tinybvh only exposes two builders, mapped to `Medium` and
+/// `SpatialSplits`. Other presets are unsupported, and trigger a debug assertion,
+/// see [`BLASBuilder::supports`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlasBuildQuality {
+    /// For interactive edits, e.g., while dragging geometry.
+    Fast,
+    /// Default preset, for every backend.
+    #[default]
+    Medium,
+    High,
+    /// Highest quality, splitting large triangles spatially.
+    ///
+    /// Triangles might be referenced by multiple leaves.
+    SpatialSplits,
+}
+
+/// Options used when adding a BVH to a [`BLASArray`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlasBuildOptions {
+    pub backend: BLASBuilder,
+    pub quality: BlasBuildQuality,
+    /// Maximum number of primitives per leaf, in `[1, 3]`.
+    ///
+    /// CWBVH leaves can't hold more than 3 primitives.
+    ///
+    /// Note: tinybvh only supports `3`.
+    pub max_leaf_size: u32,
+}
+
+impl BlasBuildOptions {
+    /// Fastest build, always using obvhs since tinybvh has no fast preset.
+    pub fn fast() -> Self {
+        Self {
+            backend: BLASBuilder::Obvhs,
+            quality: BlasBuildQuality::Fast,
+            ..Default::default()
+        }
+    }
+
+    pub fn high_quality() -> Self {
+        Self {
+            quality: BlasBuildQuality::SpatialSplits,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for BlasBuildOptions {
+    fn default() -> Self {
+        Self {
+            backend: BLASBuilder::current(),
+            quality: BlasBuildQuality::default(),
+            max_leaf_size: 3,
+        }
+    }
+}
+
+/// Type of primitives referenced by a BVH.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BLASKind {
+    /// Triangles, stored in [`BLASArray::primitives`].
+    #[default]
+    Triangles = 0,
+    /// Spheres and boxes, stored in [`BLASArray::procedurals`].
+    Procedural = 1,
+}
+
+impl BLASKind {
+    pub(crate) fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Triangles),
+            1 => Some(Self::Procedural),
+            _ => None,
+        }
+    }
+}
+
 /// Node, vertex, and index offset of an entry
 ///
 /// This is used to retrieve a flattened BVH into a buffer
@@ -26,6 +172,32 @@ pub struct BLASEntryDescriptor {
     pub node: u32,
     pub primitive: u32,
     pub vertex: u32,
+    pub procedural: u32,
+    pub kind: BLASKind,
+    /// Builder of the nodes. Procedural entries are always built with obvhs.
+    pub builder: BLASBuilder,
+    /// Bounds of the entry, in model space.
+    pub aabb: AABB,
+}
+
+impl BLASEntryDescriptor {
+    /// Offset of the first primitive, in the buffer matching the entry kind.
+    fn primitive_offset(&self) -> u32 {
+        match self.kind {
+            BLASKind::Triangles => self.primitive,
+            BLASKind::Procedural => self.procedural,
+        }
+    }
+}
+
+/// Byte ranges of [`BLASArray`] buffers modified by a refit.
+///
+/// Ranges can be used to only upload the modified part of each GPU buffer.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct BLASRefitRanges {
+    pub nodes: Range<u64>,
+    pub primitives: Range<u64>,
+    pub vertices: Range<u64>,
 }
 
 /// Data-oriented storage for a list of BVH.
@@ -47,11 +219,28 @@ pub struct BLASArray {
     /// List of indices of all entries
     pub primitives: Vec<BVHPrimitive>,
     pub vertices: Vec<Vertex>,
+    /// List of analytic primitives of all procedural entries
+    pub procedurals: Vec<ProceduralPrimitive>,
     pub instances: Vec<Instance>,
+    /// Entries to release on the next [`BLASArray::compact`]
+    removed_entries: BTreeSet<usize>,
+    /// Instances to release on the next [`BLASArray::compact`]
+    removed_instances: BTreeSet<usize>,
+}
+
+/// Mapping from old to new indices, returned by [`BLASArray::compact`].
+///
+/// `None` is used for removed items.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct BLASRemap {
+    pub entries: Vec<Option<u32>>,
+    pub instances: Vec<Option<u32>>,
 }
 
-#[cfg(not(feature = "tinybvh"))]
-fn obvh_to_tinybvh(positions: pas::Slice<'_, [f32; 4]>) -> (Vec<BVHNode>, Vec<BVHPrimitive>) {
+fn obvh_to_tinybvh(
+    positions: pas::Slice<'_, [f32; 4]>,
+    options: &BlasBuildOptions,
+) -> (Vec<BVHNode>, Vec<BVHPrimitive>) {
     // TODO: This should be heavily optimized.
     // - obvh allows to directly build from AABB
     // - Make a PR to obvh to allow using positions
@@ -71,10 +260,45 @@ fn obvh_to_tinybvh(positions: pas::Slice<'_, [f32; 4]>) -> (Vec<BVHNode>, Vec<BV
     // let tris = generate_cornell_box();
     let bvh = obvhs::cwbvh::builder::build_cwbvh_from_tris(
         &tris,
-        obvhs::BvhBuildParams::medium_build(),
+        obvh_build_params(options),
         &mut std::time::Duration::default(),
     );
 
+    let nodes = obvh_nodes(&bvh);
+
+    let mut primitives: Vec<BVHPrimitive> = Vec::with_capacity(bvh.primitive_indices.len());
+    for index in bvh.primitive_indices {
+        let tri = &tris[index as usize];
+        let edge_1 = tri.v2 - tri.v0;
+        let edge_2 = tri.v1 - tri.v0;
+        primitives.push(BVHPrimitive {
+            edge_1: edge_1.to_array(),
+            padding_0: 0,
+            edge_2: edge_2.to_array(),
+            padding_1: 0,
+            vertex_0: tri.v0.to_array(),
+            original_primitive: index,
+        });
+    }
+
+    (nodes, primitives)
+}
+
+fn obvh_build_params(options: &BlasBuildOptions) -> obvhs::BvhBuildParams {
+    let mut params = match options.quality {
+        BlasBuildQuality::Fast => obvhs::BvhBuildParams::fast_build(),
+        BlasBuildQuality::Medium => obvhs::BvhBuildParams::medium_build(),
+        BlasBuildQuality::High => obvhs::BvhBuildParams {
+            pre_split: false,
+            ..obvhs::BvhBuildParams::slow_build()
+        },
+        BlasBuildQuality::SpatialSplits => obvhs::BvhBuildParams::very_slow_build(),
+    };
+    params.max_prims_per_leaf = options.max_leaf_size.clamp(1, 3);
+    params
+}
+
+fn obvh_nodes(bvh: &obvhs::cwbvh::CwBvh) -> Vec<BVHNode> {
     let mut nodes = Vec::with_capacity(bvh.nodes.len());
     for node in &bvh.nodes {
         nodes.push(BVHNode {
@@ -98,23 +322,197 @@ fn obvh_to_tinybvh(positions: pas::Slice<'_, [f32; 4]>) -> (Vec<BVHNode>, Vec<BV
             qhi_z: node.child_max_z,
         });
     }
+    nodes
+}
 
-    let mut primitives: Vec<BVHPrimitive> = Vec::with_capacity(bvh.primitive_indices.len());
-    for index in bvh.primitive_indices {
-        let tri = &tris[index as usize];
-        let edge_1 = tri.v2 - tri.v0;
-        let edge_2 = tri.v1 - tri.v0;
-        primitives.push(BVHPrimitive {
-            edge_1: edge_1.to_array(),
-            padding_0: 0,
-            edge_2: edge_2.to_array(),
-            padding_1: 0,
-            vertex_0: tri.v0.to_array(),
-            original_primitive: index,
-        });
+fn build_bvh(
+    nodes: &mut Vec<BVHNode>,
+    primitives: &mut Vec<BVHPrimitive>,
+    positions: pas::Slice<[f32; 4]>,
+    options: &BlasBuildOptions,
+) {
+    // Empty entries have no node, and their instances are skipped by the TLAS.
+    if positions.len() < 3 {
+        return;
+    }
+    match options.backend {
+        #[cfg(feature = "tinybvh")]
+        BLASBuilder::TinyBVH => {
+            debug_assert!(
+                options.backend.supports(options.quality),
+                "tinybvh doesn't support the {:?} preset",
+                options.quality
+            );
+            debug_assert_eq!(
+                options.max_leaf_size, 3,
+                "tinybvh only supports leaves of 3 primitives"
+            );
+            if options.quality == BlasBuildQuality::SpatialSplits {
+                let bvh = cwbvh::BVH::new_hq(positions);
+                nodes.extend(bvh.nodes());
+                primitives.extend(bvh.primitives());
+            } else {
+                // The default tinybvh build corrupts memory on strided positions,
+                // e.g., the vertices of batches and indexed meshes.
+                let packed: Vec<[f32; 4]> = (0..positions.len()).map(|i| positions[i]).collect();
+                let bvh = cwbvh::BVH::new(pas::Slice::new(&packed, 0));
+                nodes.extend(bvh.nodes());
+                primitives.extend(bvh.primitives());
+            }
+        }
+        BLASBuilder::Obvhs => {
+            let value = obvh_to_tinybvh(positions, options);
+            nodes.extend(value.0);
+            primitives.extend(value.1);
+        }
     }
+}
 
-    (nodes, primitives)
+/// Build a BVH over analytic primitives.
+///
+/// Only obvhs supports building from bounding boxes.
+fn build_procedural_bvh(
+    nodes: &mut Vec<BVHNode>,
+    procedurals: &mut Vec<ProceduralPrimitive>,
+    primitives: &[ProceduralPrimitive],
+    quality: BlasBuildQuality,
+) {
+    if primitives.is_empty() {
+        return;
+    }
+    let aabbs: Vec<obvhs::aabb::Aabb> = primitives
+        .iter()
+        .map(|p| obvhs::aabb::Aabb {
+            min: glam::Vec3A::from_array(p.min),
+            max: glam::Vec3A::from_array(p.max),
+        })
+        .collect();
+    let bvh = obvhs::cwbvh::builder::build_cwbvh(
+        &aabbs,
+        obvh_build_params(&BlasBuildOptions {
+            backend: BLASBuilder::Obvhs,
+            quality,
+            ..Default::default()
+        }),
+        &mut std::time::Duration::default(),
+    );
+    nodes.extend(obvh_nodes(&bvh));
+    procedurals.extend(
+        bvh.primitive_indices
+            .iter()
+            .map(|&index| ProceduralPrimitive {
+                original_primitive: index,
+                ..primitives[index as usize]
+            }),
+    );
+}
+
+fn compute_aabb(positions: pas::Slice<'_, [f32; 4]>) -> AABB {
+    let mut aabb = AABB::make_empty();
+    for i in 0..positions.len() {
+        let pos = &positions[i];
+        aabb.expand_mut(&glam::Vec3::new(pos[0], pos[1], pos[2]));
+    }
+    aabb
+}
+
+/// Smallest power of two exponent such that `extent` fits in 255 steps.
+fn quantization_exponent(extent: f32) -> i32 {
+    let scale = extent / 255.0;
+    if scale <= 0.0 {
+        return -126;
+    }
+    let bits = scale.to_bits();
+    let mut exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    if bits & 0x7FFFFF != 0 {
+        exponent += 1;
+    }
+    exponent.clamp(-126, 127)
+}
+
+/// Recompute the bounds of the node at `index`, its descendants, and their primitives.
+///
+/// Returns the bounds of the node.
+fn refit_node(
+    nodes: &mut [BVHNode],
+    primitives: &mut [BVHPrimitive],
+    vertices: &[Vertex],
+    index: usize,
+) -> AABB {
+    let node = nodes[index];
+
+    let mut child_aabbs = [AABB::make_empty(); 8];
+    for (i, child_aabb) in child_aabbs.iter_mut().enumerate() {
+        let meta = node.child_meta[i];
+        if meta == 0 {
+            continue;
+        }
+        if node.imask & (1 << i) != 0 {
+            let relative_index = (node.imask as u32 & ((1 << i) - 1)).count_ones();
+            let child = (node.child_base_idx + relative_index) as usize;
+            *child_aabb = refit_node(nodes, primitives, vertices, child);
+        } else {
+            // `primitive_base_idx` is expressed in `vec4`, with three `vec4` per primitive.
+            let start = node.primitive_base_idx / 3 + (meta & 0b11111) as u32;
+            let count = (meta >> 5).count_ones();
+            for primitive in &mut primitives[start as usize..(start + count) as usize] {
+                let index = primitive.original_primitive as usize * 3;
+                let v0 = glam::Vec3::from_slice(&vertices[index].position[0..3]);
+                let v1 = glam::Vec3::from_slice(&vertices[index + 1].position[0..3]);
+                let v2 = glam::Vec3::from_slice(&vertices[index + 2].position[0..3]);
+                primitive.vertex_0 = v0.to_array();
+                primitive.edge_1 = (v2 - v0).to_array();
+                primitive.edge_2 = (v1 - v0).to_array();
+                child_aabb.expand_mut(&v0);
+                child_aabb.expand_mut(&v1);
+                child_aabb.expand_mut(&v2);
+            }
+        }
+    }
+
+    let mut aabb = AABB::make_empty();
+    for child_aabb in child_aabbs.iter().filter(|aabb| !aabb.is_empty()) {
+        aabb.join_mut(child_aabb);
+    }
+    if aabb.is_empty() {
+        return aabb;
+    }
+
+    let extent = aabb.max - aabb.min;
+    let exponents = [
+        quantization_exponent(extent.x),
+        quantization_exponent(extent.y),
+        quantization_exponent(extent.z),
+    ];
+    let scales = glam::Vec3::new(
+        f32::from_bits(((exponents[0] + 127) as u32) << 23),
+        f32::from_bits(((exponents[1] + 127) as u32) << 23),
+        f32::from_bits(((exponents[2] + 127) as u32) << 23),
+    );
+
+    let node = &mut nodes[index];
+    node.min = aabb.min.to_array();
+    node.exyz = [exponents[0] as u8, exponents[1] as u8, exponents[2] as u8];
+    for (i, child_aabb) in child_aabbs.iter().enumerate() {
+        if node.child_meta[i] == 0 || child_aabb.is_empty() {
+            continue;
+        }
+        let lo = ((child_aabb.min - aabb.min) / scales).floor();
+        let hi = ((child_aabb.max - aabb.min) / scales).ceil();
+        node.qlo_x[i] = lo.x.clamp(0.0, 255.0) as u8;
+        node.qlo_y[i] = lo.y.clamp(0.0, 255.0) as u8;
+        node.qlo_z[i] = lo.z.clamp(0.0, 255.0) as u8;
+        node.qhi_x[i] = hi.x.clamp(0.0, 255.0) as u8;
+        node.qhi_y[i] = hi.y.clamp(0.0, 255.0) as u8;
+        node.qhi_z[i] = hi.z.clamp(0.0, 255.0) as u8;
+    }
+
+    aabb
+}
+
+fn byte_range<T>(range: Range<usize>) -> Range<u64> {
+    let size = std::mem::size_of::<T>() as u64;
+    (range.start as u64 * size)..(range.end as u64 * size)
 }
 
 impl BLASArray {
@@ -124,13 +522,89 @@ impl BLASArray {
         }
     }
 
-    pub fn add_bvh(&mut self, mesh: MeshDescriptor) {
+    pub fn add_bvh(&mut self, mesh: MeshDescriptor, options: &BlasBuildOptions) {
         self.entries.push(BLASEntryDescriptor {
             node: self.nodes.len() as u32,
             primitive: self.primitives.len() as u32,
             vertex: self.vertices.len() as u32,
+            procedural: self.procedurals.len() as u32,
+            kind: BLASKind::Triangles,
+            builder: options.backend,
+            aabb: compute_aabb(mesh.positions),
         });
+        self.push_vertices(&mesh);
+        build_bvh(
+            &mut self.nodes,
+            &mut self.primitives,
+            mesh.positions,
+            options,
+        );
+    }
+
+    /// Add multiple BVH at once.
+    ///
+    /// With the `rayon` feature, BVHs are built in parallel. Entries are appended
+    /// in the order of `meshes`, independently of thread scheduling, and are
+    /// identical to the ones added one by one with [`BLASArray::add_bvh`].
+    pub fn add_bvh_batch(&mut self, meshes: &[MeshDescriptor], options: &BlasBuildOptions) {
+        // Vertices are copied first: offsets are known upfront, and the copied
+        // positions can be shared with the build threads.
+        let vertex_ranges: Vec<Range<usize>> =
+            meshes.iter().map(|mesh| self.push_vertices(mesh)).collect();
+        self.build_batch(&vertex_ranges, options);
+    }
+
+    /// Add multiple indexed BVH at once.
+    ///
+    /// See [`BLASArray::add_bvh_batch`] and [`BLASArray::add_bvh_indexed`].
+    pub fn add_bvh_indexed_batch(
+        &mut self,
+        meshes: &[IndexedMeshDescriptor],
+        options: &BlasBuildOptions,
+    ) {
+        let vertex_ranges: Vec<Range<usize>> = meshes
+            .iter()
+            .map(|desc| self.push_vertices_indexed(desc))
+            .collect();
+        self.build_batch(&vertex_ranges, options);
+    }
+
+    /// Build the BVHs of already copied vertices, and append their entries.
+    fn build_batch(&mut self, vertex_ranges: &[Range<usize>], options: &BlasBuildOptions) {
+        let vertices: &[Vertex] = &self.vertices;
+        let build = |range: &Range<usize>| {
+            let positions = pas::Slice::new(&vertices[range.clone()], 0);
+            let mut nodes = Vec::new();
+            let mut primitives = Vec::new();
+            build_bvh(&mut nodes, &mut primitives, positions, options);
+            (nodes, primitives, compute_aabb(positions))
+        };
+
+        #[cfg(feature = "rayon")]
+        let results: Vec<_> = {
+            use rayon::prelude::*;
+            vertex_ranges.par_iter().map(build).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let results: Vec<_> = vertex_ranges.iter().map(build).collect();
 
+        for (range, (nodes, primitives, aabb)) in vertex_ranges.iter().zip(results) {
+            self.entries.push(BLASEntryDescriptor {
+                node: self.nodes.len() as u32,
+                primitive: self.primitives.len() as u32,
+                vertex: range.start as u32,
+                procedural: self.procedurals.len() as u32,
+                kind: BLASKind::Triangles,
+                builder: options.backend,
+                aabb,
+            });
+            self.nodes.extend(nodes);
+            self.primitives.extend(primitives);
+        }
+    }
+
+    /// Append the vertices of `mesh`, and return their range in [`BLASArray::vertices`].
+    fn push_vertices(&mut self, mesh: &MeshDescriptor) -> Range<usize> {
         let start = self.vertices.len();
         self.vertices
             .resize(start + mesh.positions.len(), Vertex::default());
@@ -153,27 +627,37 @@ impl BLASArray {
                 vertices[i].normal[3] = uv[1];
             }
         }
-        #[cfg(feature = "tinybvh")]
-        {
-            let bvh = cwbvh::BVH::new_hq(mesh.positions);
-            self.nodes.extend(bvh.nodes());
-            self.primitives.extend(bvh.primitives());
-        }
-        #[cfg(not(feature = "tinybvh"))]
-        {
-            let value = obvh_to_tinybvh(mesh.positions);
-            self.nodes.extend(value.0);
-            self.primitives.extend(value.1);
+        if let Some(tangents) = mesh.tangents {
+            for i in 0..tangents.len() {
+                vertices[i].tangent = tangents[i];
+            }
+        } else if let (Some(normals), Some(texcoords)) = (mesh.normals, mesh.texcoords0) {
+            let tangents = generate_tangents(mesh.positions, normals, texcoords, None);
+            for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                vertex.tangent = tangent;
+            }
         }
+        start..self.vertices.len()
     }
 
-    pub fn add_bvh_indexed(&mut self, desc: IndexedMeshDescriptor) {
+    pub fn add_bvh_indexed(&mut self, desc: IndexedMeshDescriptor, options: &BlasBuildOptions) {
+        let range = self.push_vertices_indexed(&desc);
+        let positions: pas::Slice<[f32; 4]> = pas::Slice::new(&self.vertices[range.clone()], 0);
         self.entries.push(BLASEntryDescriptor {
             node: self.nodes.len() as u32,
             primitive: self.primitives.len() as u32,
-            vertex: self.vertices.len() as u32,
+            vertex: range.start as u32,
+            procedural: self.procedurals.len() as u32,
+            kind: BLASKind::Triangles,
+            builder: options.backend,
+            aabb: compute_aabb(positions),
         });
+        build_bvh(&mut self.nodes, &mut self.primitives, positions, options);
+    }
 
+    /// De-index and append the vertices of `desc`, and return their range in
+    /// [`BLASArray::vertices`].
+    fn push_vertices_indexed(&mut self, desc: &IndexedMeshDescriptor) -> Range<usize> {
         let vertex_count = desc.indices.len();
         let start = self.vertices.len();
         self.vertices
@@ -197,33 +681,558 @@ impl BLASArray {
                 vertices[i].normal[3] = uv[1];
             }
         }
+        // Tangents are generated before de-indexing, to be shared by adjacent triangles.
+        let generated = match (desc.mesh.tangents, desc.mesh.normals, desc.mesh.texcoords0) {
+            (None, Some(normals), Some(texcoords)) => Some(generate_tangents(
+                desc.mesh.positions,
+                normals,
+                texcoords,
+                Some(desc.indices),
+            )),
+            _ => None,
+        };
+        let tangents = desc
+            .mesh
+            .tangents
+            .or_else(|| generated.as_deref().map(|t| pas::Slice::new(t, 0)));
+        if let Some(tangents) = tangents {
+            for (i, index) in desc.indices.iter().enumerate() {
+                vertices[i].tangent = tangents[*index as usize];
+            }
+        }
 
-        let vertices: &[Vertex] = &self.vertices[start..];
-        let positions: pas::Slice<[f32; 4]> = pas::Slice::new(vertices, 0);
+        start..start + vertex_count
+    }
 
-        #[cfg(feature = "tinybvh")]
-        {
-            let bvh = cwbvh::BVH::new_hq(positions);
-            self.nodes.extend(bvh.nodes());
-            self.primitives.extend(bvh.primitives());
+    /// Add a BVH over analytic primitives, e.g., spheres for particles.
+    ///
+    /// Procedural BVHs are always built with obvhs, since tinybvh can't build
+    /// from bounding boxes.
+    pub fn add_bvh_procedural(
+        &mut self,
+        primitives: &[ProceduralPrimitive],
+        quality: BlasBuildQuality,
+    ) {
+        let mut aabb = AABB::make_empty();
+        for primitive in primitives {
+            aabb.join_mut(&primitive.aabb());
         }
-        #[cfg(not(feature = "tinybvh"))]
-        {
-            let value = obvh_to_tinybvh(positions);
-            self.nodes.extend(value.0);
-            self.primitives.extend(value.1);
+        self.entries.push(BLASEntryDescriptor {
+            node: self.nodes.len() as u32,
+            primitive: self.primitives.len() as u32,
+            vertex: self.vertices.len() as u32,
+            procedural: self.procedurals.len() as u32,
+            kind: BLASKind::Procedural,
+            builder: BLASBuilder::Obvhs,
+            aabb,
+        });
+        build_procedural_bvh(&mut self.nodes, &mut self.procedurals, primitives, quality);
+    }
+
+    /// Update the vertex positions of the entry at index `entry_index`.
+    ///
+    /// The topology of the BVH is preserved: node bounds and primitives
+    /// are recomputed in place. This is faster than a rebuild, at the cost
+    /// of a degrading tree quality as the mesh deforms.
+    ///
+    /// `positions` must contain as many vertices as the mesh used for [`BLASArray::add_bvh`].
+    ///
+    /// Returns the byte ranges of the buffers modified by the refit.
+    pub fn refit_entry(
+        &mut self,
+        entry_index: usize,
+        positions: pas::Slice<[f32; 4]>,
+    ) -> BLASRefitRanges {
+        let vertices = self.entry_vertices(entry_index);
+        assert_eq!(
+            positions.len(),
+            vertices.len(),
+            "refit requires the same vertex count as the original mesh"
+        );
+        self.refit_entry_with(entry_index, |i| positions[i])
+    }
+
+    /// Update the vertex positions of an entry added with [`BLASArray::add_bvh_indexed`].
+    ///
+    /// See [`BLASArray::refit_entry`].
+    pub fn refit_entry_indexed(
+        &mut self,
+        entry_index: usize,
+        desc: IndexedMeshDescriptor,
+    ) -> BLASRefitRanges {
+        let vertices = self.entry_vertices(entry_index);
+        assert_eq!(
+            desc.indices.len(),
+            vertices.len(),
+            "refit requires the same index count as the original mesh"
+        );
+        self.refit_entry_with(entry_index, |i| {
+            desc.mesh.positions[desc.indices[i] as usize]
+        })
+    }
+
+    fn refit_entry_with<F>(&mut self, entry_index: usize, position: F) -> BLASRefitRanges
+    where
+        F: Fn(usize) -> [f32; 4],
+    {
+        assert_eq!(
+            self.entries[entry_index].kind,
+            BLASKind::Triangles,
+            "procedural entries can't be refit"
+        );
+        let node_range = self.entry_nodes(entry_index);
+        let primitive_range = self.entry_primitives(entry_index);
+        let vertex_range = self.entry_vertices(entry_index);
+
+        let vertices = &mut self.vertices[vertex_range.clone()];
+        let mut aabb = AABB::make_empty();
+        for (i, vertex) in vertices.iter_mut().enumerate() {
+            let pos = position(i);
+            // The `w` component stores the `u` texture coordinate.
+            vertex.position = [pos[0], pos[1], pos[2], vertex.position[3]];
+            aabb.expand_mut(&glam::Vec3::new(pos[0], pos[1], pos[2]));
+        }
+        self.entries[entry_index].aabb = aabb;
+
+        if !node_range.is_empty() {
+            refit_node(
+                &mut self.nodes[node_range.clone()],
+                &mut self.primitives[primitive_range.clone()],
+                &self.vertices[vertex_range.clone()],
+                0,
+            );
+        }
+
+        BLASRefitRanges {
+            nodes: byte_range::<BVHNode>(node_range),
+            primitives: byte_range::<BVHPrimitive>(primitive_range),
+            vertices: byte_range::<Vertex>(vertex_range),
         }
     }
 
+    /// Range of [`BLASArray::nodes`] used by the entry at index `entry_index`.
+    pub fn entry_nodes(&self, entry_index: usize) -> Range<usize> {
+        let start = self.entries[entry_index].node as usize;
+        let end = self
+            .entries
+            .get(entry_index + 1)
+            .map_or(self.nodes.len(), |e| e.node as usize);
+        start..end
+    }
+
+    /// Range of [`BLASArray::primitives`] used by the entry at index `entry_index`.
+    pub fn entry_primitives(&self, entry_index: usize) -> Range<usize> {
+        let start = self.entries[entry_index].primitive as usize;
+        let end = self
+            .entries
+            .get(entry_index + 1)
+            .map_or(self.primitives.len(), |e| e.primitive as usize);
+        start..end
+    }
+
+    /// Range of [`BLASArray::vertices`] used by the entry at index `entry_index`.
+    pub fn entry_vertices(&self, entry_index: usize) -> Range<usize> {
+        let start = self.entries[entry_index].vertex as usize;
+        let end = self
+            .entries
+            .get(entry_index + 1)
+            .map_or(self.vertices.len(), |e| e.vertex as usize);
+        start..end
+    }
+
+    /// Range of [`BLASArray::procedurals`] used by the entry at index `entry_index`.
+    pub fn entry_procedurals(&self, entry_index: usize) -> Range<usize> {
+        let start = self.entries[entry_index].procedural as usize;
+        let end = self
+            .entries
+            .get(entry_index + 1)
+            .map_or(self.procedurals.len(), |e| e.procedural as usize);
+        start..end
+    }
+
     pub fn add_instance(&mut self, bvh_index: u32, model_to_world: glam::Mat4, material: u32) {
         let entry = self.entries.get(bvh_index as usize).unwrap();
+        let flags = match entry.kind {
+            BLASKind::Triangles => InstanceFlags::empty(),
+            BLASKind::Procedural => InstanceFlags::PROCEDURAL,
+        };
         self.instances.push(Instance {
             model_to_world,
             world_to_model: model_to_world.inverse(),
+            previous_model_to_world: model_to_world,
             material_index: material,
             bvh_root_index: entry.node,
             vertex_root_index: entry.vertex,
-            bvh_primitive_index: entry.primitive,
+            bvh_primitive_index: entry.primitive_offset(),
+            flags: flags.bits(),
+            entry_index: bvh_index,
+            ..Default::default()
         });
     }
+
+    /// Copy the current transform of all instances into the previous one.
+    ///
+    /// See [`Instance::store_previous_transform`].
+    pub fn store_previous_transforms(&mut self) {
+        for instance in &mut self.instances {
+            instance.store_previous_transform();
+        }
+    }
+
+    /// Mark the entry at index `entry_index` as removed.
+    ///
+    /// Instances referencing this entry are removed as well.
+    /// Data are kept until the next call to [`BLASArray::compact`].
+    pub fn remove_bvh(&mut self, entry_index: usize) {
+        assert!(entry_index < self.entries.len(), "entry out of bounds");
+        self.removed_entries.insert(entry_index);
+    }
+
+    /// Mark the instance at index `instance` as removed.
+    ///
+    /// Data are kept until the next call to [`BLASArray::compact`].
+    pub fn remove_instance(&mut self, instance: usize) {
+        assert!(instance < self.instances.len(), "instance out of bounds");
+        self.removed_instances.insert(instance);
+    }
+
+    /// Returns `true` if entries or instances are waiting for [`BLASArray::compact`].
+    pub fn has_pending_removals(&self) -> bool {
+        !self.removed_entries.is_empty() || !self.removed_instances.is_empty()
+    }
+
+    /// Defragment the arrays, releasing removed entries and instances.
+    ///
+    /// Offsets of the remaining instances are patched. Entries and instances
+    /// are re-indexed, and the returned [`BLASRemap`] maps old indices to new ones.
+    ///
+    /// GPU buffers, as well as the [`crate::TLAS`], must be rebuilt after compacting.
+    pub fn compact(&mut self) -> BLASRemap {
+        let mut remap = BLASRemap {
+            entries: vec![None; self.entries.len()],
+            instances: vec![None; self.instances.len()],
+        };
+
+        let mut entries = Vec::with_capacity(self.entries.len() - self.removed_entries.len());
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        let mut primitives = Vec::with_capacity(self.primitives.len());
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        let mut procedurals = Vec::with_capacity(self.procedurals.len());
+        for i in 0..self.entries.len() {
+            if self.removed_entries.contains(&i) {
+                continue;
+            }
+            remap.entries[i] = Some(entries.len() as u32);
+            entries.push(BLASEntryDescriptor {
+                node: nodes.len() as u32,
+                primitive: primitives.len() as u32,
+                vertex: vertices.len() as u32,
+                procedural: procedurals.len() as u32,
+                kind: self.entries[i].kind,
+                builder: self.entries[i].builder,
+                aabb: self.entries[i].aabb,
+            });
+            // Nodes and primitives are indexed relative to the entry start,
+            // and can be copied as-is.
+            nodes.extend_from_slice(&self.nodes[self.entry_nodes(i)]);
+            primitives.extend_from_slice(&self.primitives[self.entry_primitives(i)]);
+            vertices.extend_from_slice(&self.vertices[self.entry_vertices(i)]);
+            procedurals.extend_from_slice(&self.procedurals[self.entry_procedurals(i)]);
+        }
+
+        let mut instances = Vec::with_capacity(self.instances.len());
+        for (i, instance) in self.instances.iter().enumerate() {
+            if self.removed_instances.contains(&i) {
+                continue;
+            }
+            let Some(entry_index) = self.entry_index(instance).and_then(|e| remap.entries[e])
+            else {
+                continue;
+            };
+            let entry = &entries[entry_index as usize];
+            remap.instances[i] = Some(instances.len() as u32);
+            instances.push(Instance {
+                entry_index,
+                bvh_root_index: entry.node,
+                vertex_root_index: entry.vertex,
+                bvh_primitive_index: entry.primitive_offset(),
+                ..*instance
+            });
+        }
+
+        self.entries = entries;
+        self.nodes = nodes;
+        self.primitives = primitives;
+        self.vertices = vertices;
+        self.procedurals = procedurals;
+        self.instances = instances;
+        self.removed_entries.clear();
+        self.removed_instances.clear();
+
+        remap
+    }
+
+    /// Index of the entry referenced by `instance`, if any.
+    pub fn entry_index(&self, instance: &Instance) -> Option<usize> {
+        let index = instance.entry_index as usize;
+        (index < self.entries.len()).then_some(index)
+    }
+
+    /// World space bounds of the instance at index `instance`.
+    pub fn instance_aabb(&self, instance: usize) -> AABB {
+        let instance = &self.instances[instance];
+        let Some(entry) = self.entry_index(instance) else {
+            return AABB::make_empty();
+        };
+        let local = &self.entries[entry].aabb;
+        let mut aabb = AABB::make_empty();
+        if local.is_empty() {
+            return aabb;
+        }
+        for i in 0..8 {
+            let corner = glam::Vec3::new(
+                if i & 1 == 0 { local.min.x } else { local.max.x },
+                if i & 2 == 0 { local.min.y } else { local.max.y },
+                if i & 4 == 0 { local.min.z } else { local.max.z },
+            );
+            aabb.expand_mut(&instance.model_to_world.transform_point3(corner));
+        }
+        aabb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+
+    use super::*;
+    use crate::Ray;
+
+    /// Grid of `2 x 2` units facing `+z`, centered on `center`.
+    ///
+    /// Subdivided, since tinybvh can't build a tree made of a single leaf.
+    fn quad(center: Vec3) -> Vec<[f32; 4]> {
+        const CELLS: usize = 4;
+        let size = 2.0 / CELLS as f32;
+        let mut positions = Vec::new();
+        for y in 0..CELLS {
+            for x in 0..CELLS {
+                let min = center + Vec3::new(x as f32 * size - 1.0, y as f32 * size - 1.0, 0.0);
+                let corners = [
+                    min,
+                    min + Vec3::new(size, 0.0, 0.0),
+                    min + Vec3::new(size, size, 0.0),
+                    min + Vec3::new(0.0, size, 0.0),
+                ];
+                for i in [0, 1, 2, 0, 2, 3].iter() {
+                    let p = corners[*i];
+                    positions.push([p.x, p.y, p.z, 0.0]);
+                }
+            }
+        }
+        positions
+    }
+
+    fn mesh(positions: &[[f32; 4]]) -> MeshDescriptor<'_> {
+        MeshDescriptor {
+            positions: pas::Slice::new(positions, 0),
+            normals: None,
+            texcoords0: None,
+            tangents: None,
+        }
+    }
+
+    fn hit_instance(blas: &BLASArray, target: Vec3) -> Option<u32> {
+        // Off the edges of the grid cells.
+        let origin = target + Vec3::new(0.3, -0.1, 10.0);
+        blas.intersect(&Ray::from_origin_dir(&origin, -Vec3::Z))
+            .map(|hit| hit.instance())
+    }
+
+    #[test]
+    fn empty_entry_and_compact() {
+        let centers = [
+            Vec3::new(-6.0, 0.0, 0.0),
+            Vec3::ZERO,
+            Vec3::new(6.0, 0.0, 0.0),
+        ];
+        let quads: Vec<Vec<[f32; 4]>> = centers.iter().map(|c| quad(*c)).collect();
+        let options = BlasBuildOptions {
+            quality: BlasBuildQuality::Medium,
+            ..Default::default()
+        };
+
+        let mut blas = BLASArray::new();
+        blas.add_bvh(mesh(&quads[0]), &options);
+        blas.add_bvh(mesh(&[]), &options);
+        blas.add_bvh(mesh(&quads[1]), &options);
+        blas.add_bvh(mesh(&quads[2]), &options);
+        for entry in 0..4 {
+            blas.add_instance(entry, Mat4::IDENTITY, 0);
+        }
+
+        // The empty entry shares its node offset with the following one.
+        assert!(blas.entry_nodes(1).is_empty());
+        assert_eq!(blas.entries[1].node, blas.entries[2].node);
+        for (i, instance) in blas.instances.iter().enumerate() {
+            assert_eq!(blas.entry_index(instance), Some(i));
+        }
+        assert!(blas.instance_aabb(1).is_empty());
+        assert!(!blas.instance_aabb(2).is_empty());
+        assert_eq!(hit_instance(&blas, centers[1]), Some(2));
+
+        // Remove an entry in the middle, and the first instance.
+        blas.remove_bvh(2);
+        blas.remove_instance(0);
+        let remap = blas.compact();
+        assert_eq!(remap.entries, vec![Some(0), Some(1), None, Some(2)]);
+        assert_eq!(remap.instances, vec![None, Some(0), None, Some(1)]);
+
+        assert_eq!(blas.entries.len(), 3);
+        assert_eq!(blas.instances.len(), 2);
+        assert_eq!(blas.entry_index(&blas.instances[0]), Some(1));
+        assert_eq!(blas.entry_index(&blas.instances[1]), Some(2));
+        for instance in &blas.instances {
+            let entry = &blas.entries[instance.entry_index as usize];
+            assert_eq!(instance.bvh_root_index, entry.node);
+            assert_eq!(instance.vertex_root_index, entry.vertex);
+        }
+
+        assert_eq!(hit_instance(&blas, centers[0]), None);
+        assert_eq!(hit_instance(&blas, centers[1]), None);
+        assert_eq!(hit_instance(&blas, centers[2]), Some(1));
+    }
+
+    /// Grid of `cells x cells` quads sharing their vertices.
+    struct IndexedGrid {
+        positions: Vec<[f32; 4]>,
+        normals: Vec<[f32; 3]>,
+        texcoords: Vec<[f32; 2]>,
+        indices: Vec<u32>,
+    }
+
+    impl IndexedGrid {
+        fn new(cells: u32, z: f32) -> Self {
+            let mut positions = Vec::new();
+            let mut texcoords = Vec::new();
+            for y in 0..=cells {
+                for x in 0..=cells {
+                    let uv = [x as f32 / cells as f32, y as f32 / cells as f32];
+                    positions.push([uv[0] * 2.0 - 1.0, uv[1] * 2.0 - 1.0, z, 0.0]);
+                    texcoords.push(uv);
+                }
+            }
+            let mut indices = Vec::new();
+            for y in 0..cells {
+                for x in 0..cells {
+                    let i = y * (cells + 1) + x;
+                    let j = i + cells + 1;
+                    indices.extend_from_slice(&[i, i + 1, j + 1, i, j + 1, j]);
+                }
+            }
+            Self {
+                normals: vec![[0.0, 0.0, 1.0]; positions.len()],
+                positions,
+                texcoords,
+                indices,
+            }
+        }
+
+        fn descriptor(&self) -> IndexedMeshDescriptor<'_> {
+            IndexedMeshDescriptor {
+                mesh: MeshDescriptor {
+                    positions: pas::Slice::new(&self.positions, 0),
+                    normals: Some(pas::Slice::new(&self.normals, 0)),
+                    texcoords0: Some(pas::Slice::new(&self.texcoords, 0)),
+                    tangents: None,
+                },
+                indices: &self.indices,
+            }
+        }
+    }
+
+    fn bytes<T: bytemuck::Pod>(data: &[T]) -> &[u8] {
+        bytemuck::cast_slice(data)
+    }
+
+    fn assert_same_arrays(a: &BLASArray, b: &BLASArray, label: &str) {
+        assert_eq!(a.entries.len(), b.entries.len(), "{}: entries", label);
+        for (x, y) in a.entries.iter().zip(b.entries.iter()) {
+            assert_eq!(
+                (x.node, x.primitive, x.vertex, x.procedural),
+                (y.node, y.primitive, y.vertex, y.procedural),
+                "{}: entry offsets",
+                label
+            );
+            assert_eq!(
+                (x.aabb.min, x.aabb.max),
+                (y.aabb.min, y.aabb.max),
+                "{}: aabb",
+                label
+            );
+        }
+        assert!(bytes(&a.nodes) == bytes(&b.nodes), "{}: nodes", label);
+        assert!(
+            bytes(&a.primitives) == bytes(&b.primitives),
+            "{}: primitives",
+            label
+        );
+        assert!(
+            bytes(&a.vertices) == bytes(&b.vertices),
+            "{}: vertices",
+            label
+        );
+    }
+
+    /// Batches are compared against sequential builds, with and without the
+    /// `rayon` feature, and thus match across both configurations.
+    #[test]
+    fn batch_matches_sequential() {
+        let mut backends = vec![BLASBuilder::Obvhs];
+        #[cfg(feature = "tinybvh")]
+        backends.push(BLASBuilder::TinyBVH);
+
+        let quads: Vec<Vec<[f32; 4]>> = (0..6)
+            .map(|i| quad(Vec3::new(i as f32 * 3.0, 0.0, i as f32)))
+            .collect();
+        let grids: Vec<IndexedGrid> = (4..8).map(|i| IndexedGrid::new(i, i as f32)).collect();
+        let indexed: Vec<IndexedMeshDescriptor> = grids.iter().map(|g| g.descriptor()).collect();
+
+        for backend in backends {
+            let options = BlasBuildOptions {
+                backend,
+                ..Default::default()
+            };
+            let mut meshes: Vec<MeshDescriptor> = quads.iter().map(|q| mesh(q)).collect();
+            meshes.insert(2, mesh(&[]));
+
+            let mut sequential = BLASArray::new();
+            for m in meshes.iter() {
+                sequential.add_bvh(*m, &options);
+            }
+            let mut batch = BLASArray::new();
+            batch.add_bvh_batch(&meshes, &options);
+            assert_same_arrays(&sequential, &batch, &format!("{:?}", backend));
+
+            let mut sequential = BLASArray::new();
+            for desc in indexed.iter() {
+                sequential.add_bvh_indexed(*desc, &options);
+            }
+            let mut batch = BLASArray::new();
+            batch.add_bvh_indexed_batch(&indexed, &options);
+            assert_same_arrays(&sequential, &batch, &format!("{:?} indexed", backend));
+        }
+    }
+
+    #[cfg(all(feature = "tinybvh", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "tinybvh doesn't support the Fast preset")]
+    fn tinybvh_unsupported_quality() {
+        let options = BlasBuildOptions {
+            backend: BLASBuilder::TinyBVH,
+            quality: BlasBuildQuality::Fast,
+            ..Default::default()
+        };
+        BLASArray::new().add_bvh(mesh(&quad(Vec3::ZERO)), &options);
+    }
 }
@@ -0,0 +1,529 @@
+use std::hash::Hasher;
+use std::io::Write;
+
+use albedo_math::AABB;
+
+use crate::{
+    BLASArray, BLASBuilder, BLASEntryDescriptor, BLASKind, BVHNode, BVHPrimitive,
+    IndexedMeshDescriptor, Instance, MeshDescriptor, ProceduralPrimitive, Vertex,
+};
+
+/// Version of the binary layout.
+///
+/// Must be bumped whenever the layout of the header, or of any
+/// serialized GPU struct, changes.
+pub const BLAS_CACHE_VERSION: u32 = 1;
+
+const BLAS_CACHE_MAGIC: [u8; 4] = *b"ABLS";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BLASCacheError {
+    /// Data doesn't start with the cache signature.
+    InvalidMagic,
+    /// Cache was written with another layout version.
+    Version(u32),
+    /// An entry was built by a builder unavailable in this build,
+    /// e.g., tinybvh when the `tinybvh` feature is disabled.
+    Builder(u32),
+    /// An entry has an unknown [`BLASKind`].
+    Kind(u32),
+    /// Cache was built from a different source mesh.
+    Stale,
+    /// Data is smaller than described by the header.
+    Truncated,
+    /// Data isn't aligned on 16 bytes and can't be read in place.
+    Misaligned,
+}
+
+impl std::fmt::Display for BLASCacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "Invalid magic: data isn't a BLAS cache"),
+            Self::Version(version) => write!(
+                f,
+                "Version mismatch: expected {}, found {}",
+                BLAS_CACHE_VERSION, version
+            ),
+            Self::Builder(builder) => write!(f, "Unsupported builder: {}", builder),
+            Self::Kind(kind) => write!(f, "Unknown entry kind: {}", kind),
+            Self::Stale => write!(f, "Stale: content hash mismatch"),
+            Self::Truncated => write!(f, "Truncated: data is smaller than described"),
+            Self::Misaligned => write!(f, "Misaligned: data must be aligned on 16 bytes"),
+        }
+    }
+}
+
+impl std::error::Error for BLASCacheError {}
+
+/// 64-bit FNV-1a hasher.
+///
+/// Used to hash the source mesh data. At the opposite of
+/// [`std::collections::hash_map::DefaultHasher`], the output is stable
+/// across Rust versions and platforms.
+pub struct ContentHasher(u64);
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl ContentHasher {
+    /// Hash the attributes of `mesh`.
+    ///
+    /// Meshes of a [`BLASArray`] can be written one after the other,
+    /// and [`Hasher::finish`] used as the content hash of the cache.
+    pub fn write_mesh(&mut self, mesh: &MeshDescriptor) {
+        self.write_attribute(Some(mesh.positions));
+        self.write_attribute(mesh.normals);
+        self.write_attribute(mesh.texcoords0);
+        self.write_attribute(mesh.tangents);
+    }
+
+    /// Hash the attributes and indices of `desc`.
+    pub fn write_indexed_mesh(&mut self, desc: &IndexedMeshDescriptor) {
+        self.write_mesh(&desc.mesh);
+        self.write_u64(desc.indices.len() as u64);
+        self.write(bytemuck::cast_slice(desc.indices));
+    }
+
+    fn write_attribute<T: bytemuck::Pod>(&mut self, attribute: Option<pas::Slice<T>>) {
+        // The length distinguishes missing from empty attributes.
+        let Some(attribute) = attribute else {
+            self.write_u64(u64::MAX);
+            return;
+        };
+        self.write_u64(attribute.len() as u64);
+        for i in 0..attribute.len() {
+            self.write(bytemuck::bytes_of(&attribute[i]));
+        }
+    }
+}
+
+impl Hasher for ContentHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+    padding_0: [u32; 2],
+    content_hash: u64,
+    padding_1: u64,
+    entry_count: u32,
+    node_count: u32,
+    primitive_count: u32,
+    vertex_count: u32,
+    instance_count: u32,
+    procedural_count: u32,
+    padding_2: [u32; 2],
+}
+
+/// Serialized [`BLASEntryDescriptor`].
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct BLASCacheEntry {
+    pub node: u32,
+    pub primitive: u32,
+    pub vertex: u32,
+    pub procedural: u32,
+    pub min: [f32; 3],
+    /// [`BLASKind`] of the entry.
+    pub kind: u32,
+    pub max: [f32; 3],
+    /// [`BLASBuilder`] of the entry nodes.
+    pub builder: u32,
+}
+
+impl BLASCacheEntry {
+    /// Kind of the entry, `None` if unknown.
+    pub fn kind(&self) -> Option<BLASKind> {
+        BLASKind::from_u32(self.kind)
+    }
+
+    /// Builder of the entry, `None` if unavailable in this build.
+    pub fn builder(&self) -> Option<BLASBuilder> {
+        BLASBuilder::from_u32(self.builder)
+    }
+}
+
+impl From<&BLASEntryDescriptor> for BLASCacheEntry {
+    fn from(entry: &BLASEntryDescriptor) -> Self {
+        Self {
+            node: entry.node,
+            primitive: entry.primitive,
+            vertex: entry.vertex,
+            procedural: entry.procedural,
+            min: entry.aabb.min.to_array(),
+            kind: entry.kind as u32,
+            max: entry.aabb.max.to_array(),
+            builder: entry.builder as u32,
+        }
+    }
+}
+
+impl From<&BLASCacheEntry> for BLASEntryDescriptor {
+    fn from(entry: &BLASCacheEntry) -> Self {
+        Self {
+            node: entry.node,
+            primitive: entry.primitive,
+            vertex: entry.vertex,
+            procedural: entry.procedural,
+            kind: entry
+                .kind()
+                .expect("kind is validated by `BLASCache::from_bytes`"),
+            builder: entry
+                .builder()
+                .expect("builder is validated by `BLASCache::from_bytes`"),
+            aabb: AABB::from_points(entry.min.into(), entry.max.into()),
+        }
+    }
+}
+
+/// Read-only view over a serialized [`BLASArray`].
+///
+/// Slices point directly into the cache data, and can be uploaded
+/// without copy, e.g., using `gpu::Buffer::new_storage_with_data`.
+///
+/// Layout:
+///
+/// `[header, entries, nodes, primitives, vertices, instances, procedurals]`
+///
+/// All sections are 16 bytes aligned.
+pub struct BLASCache<'a> {
+    pub content_hash: u64,
+    pub entries: &'a [BLASCacheEntry],
+    pub nodes: &'a [BVHNode],
+    pub primitives: &'a [BVHPrimitive],
+    pub vertices: &'a [Vertex],
+    pub instances: &'a [Instance],
+    pub procedurals: &'a [ProceduralPrimitive],
+}
+
+impl<'a> BLASCache<'a> {
+    /// Validate and read `data` in place.
+    ///
+    /// `content_hash` is the hash of the source mesh, and must match
+    /// the hash used when writing the cache.
+    ///
+    /// Each entry records its builder, see [`BLASCacheEntry::builder`]:
+    /// entries built by another builder than the one expected by the
+    /// application can be detected and rebuilt.
+    ///
+    /// `data` must be aligned on 16 bytes.
+    pub fn from_bytes(data: &'a [u8], content_hash: u64) -> Result<Self, BLASCacheError> {
+        let header_size = std::mem::size_of::<Header>();
+        if data.len() < header_size {
+            return Err(BLASCacheError::Truncated);
+        }
+        if data.as_ptr() as usize & 15 != 0 {
+            return Err(BLASCacheError::Misaligned);
+        }
+        let header: Header = bytemuck::pod_read_unaligned(&data[0..header_size]);
+        if header.magic != BLAS_CACHE_MAGIC {
+            return Err(BLASCacheError::InvalidMagic);
+        }
+        if header.version != BLAS_CACHE_VERSION {
+            return Err(BLASCacheError::Version(header.version));
+        }
+        if header.content_hash != content_hash {
+            return Err(BLASCacheError::Stale);
+        }
+
+        let mut offset = header_size;
+        let entries: &[BLASCacheEntry] = read_section(data, &mut offset, header.entry_count)?;
+        for entry in entries {
+            if entry.kind().is_none() {
+                return Err(BLASCacheError::Kind(entry.kind));
+            }
+            if entry.builder().is_none() {
+                return Err(BLASCacheError::Builder(entry.builder));
+            }
+        }
+        let nodes = read_section(data, &mut offset, header.node_count)?;
+        let primitives = read_section(data, &mut offset, header.primitive_count)?;
+        let vertices = read_section(data, &mut offset, header.vertex_count)?;
+        let instances = read_section(data, &mut offset, header.instance_count)?;
+        let procedurals = read_section(data, &mut offset, header.procedural_count)?;
+
+        Ok(Self {
+            content_hash,
+            entries,
+            nodes,
+            primitives,
+            vertices,
+            instances,
+            procedurals,
+        })
+    }
+}
+
+fn read_section<'a, T: bytemuck::Pod>(
+    data: &'a [u8],
+    offset: &mut usize,
+    count: u32,
+) -> Result<&'a [T], BLASCacheError> {
+    let size = count as usize * std::mem::size_of::<T>();
+    let end = *offset + size;
+    if end > data.len() {
+        return Err(BLASCacheError::Truncated);
+    }
+    let slice =
+        bytemuck::try_cast_slice(&data[*offset..end]).map_err(|_| BLASCacheError::Misaligned)?;
+    *offset = align_16(end);
+    Ok(slice)
+}
+
+fn write_section<T: bytemuck::Pod, W: Write>(
+    writer: &mut W,
+    offset: &mut usize,
+    data: &[T],
+) -> std::io::Result<()> {
+    let bytes: &[u8] = bytemuck::cast_slice(data);
+    writer.write_all(bytes)?;
+    let end = *offset + bytes.len();
+    *offset = align_16(end);
+    writer.write_all(&[0; 16][..*offset - end])
+}
+
+fn align_16(offset: usize) -> usize {
+    (offset + 15) & !15
+}
+
+impl BLASArray {
+    /// Serialize the array.
+    ///
+    /// `content_hash` identifies the source mesh data, see [`ContentHasher`].
+    ///
+    /// Removed entries and instances must be released with [`BLASArray::compact`] first.
+    pub fn write_cache<W: Write>(&self, writer: &mut W, content_hash: u64) -> std::io::Result<()> {
+        assert!(
+            !self.has_pending_removals(),
+            "compact() must be called before serializing"
+        );
+
+        let entries: Vec<BLASCacheEntry> = self.entries.iter().map(|e| e.into()).collect();
+        let header = Header {
+            magic: BLAS_CACHE_MAGIC,
+            version: BLAS_CACHE_VERSION,
+            content_hash,
+            entry_count: entries.len() as u32,
+            node_count: self.nodes.len() as u32,
+            primitive_count: self.primitives.len() as u32,
+            vertex_count: self.vertices.len() as u32,
+            instance_count: self.instances.len() as u32,
+            procedural_count: self.procedurals.len() as u32,
+            ..bytemuck::Zeroable::zeroed()
+        };
+
+        let mut offset = 0;
+        write_section(writer, &mut offset, std::slice::from_ref(&header))?;
+        write_section(writer, &mut offset, &entries)?;
+        write_section(writer, &mut offset, &self.nodes)?;
+        write_section(writer, &mut offset, &self.primitives)?;
+        write_section(writer, &mut offset, &self.vertices)?;
+        write_section(writer, &mut offset, &self.instances)?;
+        write_section(writer, &mut offset, &self.procedurals)
+    }
+
+    /// Create an array from a cache, copying the data.
+    pub fn from_cache(cache: &BLASCache) -> Self {
+        let mut blas = BLASArray::new();
+        blas.entries = cache.entries.iter().map(|e| e.into()).collect();
+        blas.nodes = cache.nodes.to_vec();
+        blas.primitives = cache.primitives.to_vec();
+        blas.vertices = cache.vertices.to_vec();
+        blas.instances = cache.instances.to_vec();
+        blas.procedurals = cache.procedurals.to_vec();
+        blas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::Hasher;
+
+    use glam::{Mat4, Vec3};
+
+    use super::*;
+    use crate::{BlasBuildOptions, BlasBuildQuality};
+
+    /// Grid of `cells x cells` quads on the `xy` plane, with a bump in `z`.
+    fn grid(cells: usize) -> Vec<[f32; 4]> {
+        let mut positions = Vec::new();
+        let p = |x: usize, y: usize| {
+            let (x, y) = (x as f32, y as f32);
+            [x, y, (x * 0.7).sin() * (y * 0.3).cos(), 0.0]
+        };
+        for y in 0..cells {
+            for x in 0..cells {
+                for (dx, dy) in [(0, 0), (1, 0), (1, 1), (0, 0), (1, 1), (0, 1)].iter() {
+                    positions.push(p(x + dx, y + dy));
+                }
+            }
+        }
+        positions
+    }
+
+    fn mesh(positions: &[[f32; 4]]) -> MeshDescriptor<'_> {
+        MeshDescriptor {
+            positions: pas::Slice::new(positions, 0),
+            normals: None,
+            texcoords0: None,
+            tangents: None,
+        }
+    }
+
+    fn scene(positions: &[[f32; 4]]) -> (BLASArray, u64) {
+        let mut blas = BLASArray::new();
+        blas.add_bvh(mesh(positions), &BlasBuildOptions::default());
+        blas.add_bvh_procedural(
+            &[
+                ProceduralPrimitive::sphere(Vec3::new(0.0, 0.0, 4.0), 1.0),
+                ProceduralPrimitive::cuboid(Vec3::splat(-1.0), Vec3::splat(1.0)),
+            ],
+            BlasBuildQuality::default(),
+        );
+        blas.add_instance(0, Mat4::IDENTITY, 0);
+        blas.add_instance(1, Mat4::from_translation(Vec3::X * 10.0), 1);
+
+        let mut hasher = ContentHasher::default();
+        hasher.write_mesh(&mesh(positions));
+        (blas, hasher.finish())
+    }
+
+    /// Copy `bytes` at `offset` bytes from a 16 bytes boundary.
+    fn aligned(bytes: &[u8], offset: usize) -> Vec<u128> {
+        let mut storage = vec![0u128; (bytes.len() + offset).div_ceil(16)];
+        bytemuck::cast_slice_mut::<u128, u8>(&mut storage)[offset..offset + bytes.len()]
+            .copy_from_slice(bytes);
+        storage
+    }
+
+    fn bytes<T: bytemuck::Pod>(data: &[T]) -> &[u8] {
+        bytemuck::cast_slice(data)
+    }
+
+    fn write(blas: &BLASArray, hash: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        blas.write_cache(&mut data, hash).unwrap();
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let positions = grid(8);
+        let (blas, hash) = scene(&positions);
+        let data = write(&blas, hash);
+        let storage = aligned(&data, 0);
+
+        let cache =
+            BLASCache::from_bytes(&bytemuck::cast_slice(&storage)[..data.len()], hash).unwrap();
+        let read = BLASArray::from_cache(&cache);
+
+        assert_eq!(read.entries.len(), blas.entries.len());
+        for (a, b) in read.entries.iter().zip(&blas.entries) {
+            assert_eq!(
+                bytes(&[BLASCacheEntry::from(a)]),
+                bytes(&[BLASCacheEntry::from(b)])
+            );
+            assert_eq!(a.kind, b.kind);
+            assert_eq!(a.builder, b.builder);
+        }
+        assert_eq!(read.entries[1].builder, BLASBuilder::Obvhs);
+        assert_eq!(bytes(&read.nodes), bytes(&blas.nodes));
+        assert_eq!(bytes(&read.primitives), bytes(&blas.primitives));
+        assert_eq!(bytes(&read.vertices), bytes(&blas.vertices));
+        assert_eq!(bytes(&read.instances), bytes(&blas.instances));
+        assert_eq!(bytes(&read.procedurals), bytes(&blas.procedurals));
+
+        // Writing the read array gives back the same bytes.
+        assert_eq!(write(&read, hash), data);
+    }
+
+    #[test]
+    fn content_hash() {
+        let positions = grid(2);
+        let hash = |positions: &[[f32; 4]], indices: Option<&[u32]>| {
+            let mut hasher = ContentHasher::default();
+            match indices {
+                Some(indices) => hasher.write_indexed_mesh(&IndexedMeshDescriptor {
+                    mesh: mesh(positions),
+                    indices,
+                }),
+                None => hasher.write_mesh(&mesh(positions)),
+            }
+            hasher.finish()
+        };
+        let reference = hash(&positions, None);
+        assert_eq!(hash(&positions, None), reference);
+
+        let mut moved = positions.clone();
+        moved[3][1] += 0.001;
+        assert_ne!(hash(&moved, None), reference);
+        assert_ne!(hash(&positions, Some(&[0, 1, 2])), reference);
+        assert_ne!(
+            hash(&positions, Some(&[0, 1, 2])),
+            hash(&positions, Some(&[0, 2, 1]))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let positions = grid(8);
+        let (blas, hash) = scene(&positions);
+        let data = write(&blas, hash);
+        let read = |data: &[u8], hash: u64| {
+            let storage = aligned(data, 0);
+            BLASCache::from_bytes(&bytemuck::cast_slice(&storage)[..data.len()], hash).err()
+        };
+
+        assert_eq!(read(&data, hash), None);
+        assert_eq!(read(&data, hash ^ 1), Some(BLASCacheError::Stale));
+
+        let mut invalid = data.clone();
+        invalid[0] = b'X';
+        assert_eq!(read(&invalid, hash), Some(BLASCacheError::InvalidMagic));
+
+        let mut invalid = data.clone();
+        invalid[4..8].copy_from_slice(&(BLAS_CACHE_VERSION + 1).to_ne_bytes());
+        assert_eq!(
+            read(&invalid, hash),
+            Some(BLASCacheError::Version(BLAS_CACHE_VERSION + 1))
+        );
+
+        let kind = std::mem::size_of::<Header>() + std::mem::offset_of!(BLASCacheEntry, kind);
+        let mut invalid = data.clone();
+        invalid[kind..kind + 4].copy_from_slice(&7u32.to_ne_bytes());
+        assert_eq!(read(&invalid, hash), Some(BLASCacheError::Kind(7)));
+
+        let builder = std::mem::size_of::<Header>() + std::mem::offset_of!(BLASCacheEntry, builder);
+        let mut invalid = data.clone();
+        invalid[builder..builder + 4].copy_from_slice(&0u32.to_ne_bytes());
+        assert_eq!(read(&invalid, hash), Some(BLASCacheError::Builder(0)));
+
+        assert_eq!(read(&data[..8], hash), Some(BLASCacheError::Truncated));
+        assert_eq!(
+            read(&data[..data.len() - 16], hash),
+            Some(BLASCacheError::Truncated)
+        );
+
+        let storage = aligned(&data, 1);
+        let misaligned = &bytemuck::cast_slice::<u128, u8>(&storage)[1..data.len() + 1];
+        assert_eq!(
+            BLASCache::from_bytes(misaligned, hash).err(),
+            Some(BLASCacheError::Misaligned)
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use glam::Vec3;
+
+use crate::{BLASArray, BLASKind, EmissiveTriangle, Material};
+
+impl BLASArray {
+    /// Collect the triangles of instances using an emissive material.
+    ///
+    /// Triangles are transformed in world space, and must be collected again
+    /// when instances move. Procedural instances are skipped, and degenerate
+    /// triangles as well.
+    ///
+    /// The emissive texture isn't taken into account.
+    pub fn emissive_triangles(&self, materials: &[Material]) -> Vec<EmissiveTriangle> {
+        let mut triangles: Vec<EmissiveTriangle> = Vec::new();
+
+        for (i, instance) in self.instances.iter().enumerate() {
+            let Some(material) = materials.get(instance.material_index as usize) else {
+                continue;
+            };
+            if !material.is_emissive() {
+                continue;
+            }
+            let Some(entry) = self.entry_index(instance) else {
+                continue;
+            };
+            if self.entries[entry].kind != BLASKind::Triangles {
+                continue;
+            }
+
+            let emission = material.emission();
+            let vertices = &self.vertices[self.entry_vertices(entry)];
+            for (index, triangle) in vertices.chunks_exact(3).enumerate() {
+                let [v0, v1, v2] = [&triangle[0], &triangle[1], &triangle[2]].map(|v| {
+                    instance
+                        .model_to_world
+                        .transform_point3(Vec3::from(*v.position()))
+                });
+                let area = 0.5 * (v1 - v0).cross(v2 - v0).length();
+                if area <= 0.0 {
+                    continue;
+                }
+                triangles.push(EmissiveTriangle {
+                    v0: v0.to_array(),
+                    instance: i as u32,
+                    v1: v1.to_array(),
+                    index: index as u32 * 3,
+                    v2: v2.to_array(),
+                    area,
+                    emission: emission.to_array(),
+                });
+            }
+        }
+        triangles
+    }
+}
@@ -0,0 +1,638 @@
+use glam::{Vec2, Vec3};
+
+/// Importance sampling distribution of an equirectangular probe.
+///
+/// Texels are weighted by their luminance, and by the solid angle they cover.
+/// Uploaded as-is, and bound with the probe, see
+/// [`crate::RTSurfaceBindGroupLayout::create_bindgroup`].
+pub struct ProbeDistribution {
+    pub width: u32,
+    pub height: u32,
+    /// Marginal CDF of the rows, followed by the conditional CDF of each row.
+    ///
+    /// Contains `height + width * height` values.
+    pub cdf: Vec<f32>,
+}
+
+impl ProbeDistribution {
+    /// Build the distribution from linear radiance texels, stored row by row.
+    pub fn new(width: u32, height: u32, texels: &[[f32; 3]]) -> Self {
+        let (w, h) = (width as usize, height as usize);
+        assert_eq!(texels.len(), w * h, "texel count must match the probe size");
+
+        let mut cdf = vec![0.0; h + w * h];
+        let (marginal, conditionals) = cdf.split_at_mut(h);
+
+        let mut total = 0.0;
+        for (y, row) in conditionals.chunks_exact_mut(w).enumerate() {
+            // Rows close to the poles cover a smaller solid angle.
+            let sin_theta = (std::f32::consts::PI * (y as f32 + 0.5) / h as f32).sin();
+            let mut sum = 0.0;
+            for (x, value) in row.iter_mut().enumerate() {
+                sum += luminance(texels[y * w + x]) * sin_theta;
+                *value = sum;
+            }
+            normalize_cdf(row, sum);
+            total += sum;
+            marginal[y] = total;
+        }
+        normalize_cdf(marginal, total);
+
+        Self { width, height, cdf }
+    }
+
+    /// Build the distribution from RGBE8 texels, as expected by the shaders.
+    pub fn from_rgbe8(width: u32, height: u32, texels: &[[u8; 4]]) -> Self {
+        let texels: Vec<[f32; 3]> = texels.iter().map(decode_rgbe8).collect();
+        Self::new(width, height, &texels)
+    }
+
+    /// Solid angle pdf of sampling `dir`.
+    ///
+    /// Port of `probePdf` in `shading.comp`, without the probe rotation.
+    pub fn pdf(&self, dir: Vec3) -> f32 {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let uv = cartesian_to_equi(dir.normalize());
+        let x = ((uv.x * w as f32) as usize).min(w - 1);
+        let y = ((uv.y * h as f32) as usize).min(h - 1);
+
+        let sin_latitude = (uv.y * std::f32::consts::PI).sin();
+        if sin_latitude <= SAMPLING_EPSILON {
+            return 0.0;
+        }
+
+        let (marginal, conditionals) = self.cdf.split_at(h);
+        let row = &conditionals[y * w..(y + 1) * w];
+        let pdf_row = marginal[y] - cdf_start(marginal, y);
+        let pdf_column = row[x] - cdf_start(row, x);
+        pdf_row * pdf_column * (w * h) as f32
+            / (2.0 * std::f32::consts::PI * std::f32::consts::PI * sin_latitude)
+    }
+
+    /// Direction sampled with the random numbers `r`, in `[0; 1)`.
+    ///
+    /// Port of `sampleProbeLight` in `shading.comp`, without the probe rotation.
+    pub fn sample(&self, r: Vec2) -> Vec3 {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (marginal, conditionals) = self.cdf.split_at(h);
+        let y = search_cdf(marginal, r.y);
+        let row = &conditionals[y * w..(y + 1) * w];
+        let x = search_cdf(row, r.x);
+
+        // Re-use the random numbers to jitter inside the texel.
+        let start = Vec2::new(cdf_start(row, x), cdf_start(marginal, y));
+        let end = Vec2::new(row[x], marginal[y]);
+        let jitter = ((r - start) / (end - start).max(Vec2::splat(SAMPLING_EPSILON)))
+            .clamp(Vec2::ZERO, Vec2::ONE);
+        let uv = (Vec2::new(x as f32, y as f32) + jitter) / Vec2::new(w as f32, h as f32);
+        equi_to_cartesian(uv)
+    }
+}
+
+/// `EPSILON` in `common.glsl`.
+const SAMPLING_EPSILON: f32 = 0.00000001;
+
+/// Port of `searchProbeCdf` in `shading.comp`.
+fn search_cdf(cdf: &[f32], value: f32) -> usize {
+    cdf.partition_point(|&v| v <= value).min(cdf.len() - 1)
+}
+
+/// Value of the CDF before entry `index`.
+fn cdf_start(cdf: &[f32], index: usize) -> f32 {
+    if index > 0 {
+        cdf[index - 1]
+    } else {
+        0.0
+    }
+}
+
+/// Port of `decodeRGBE` in `shading.comp`.
+pub(crate) fn decode_rgbe8(texel: &[u8; 4]) -> [f32; 3] {
+    let scale = (texel[3] as f32 - 128.0).exp2() / 255.0;
+    [
+        texel[0] as f32 * scale,
+        texel[1] as f32 * scale,
+        texel[2] as f32 * scale,
+    ]
+}
+
+fn luminance(rgb: [f32; 3]) -> f32 {
+    Vec3::from(rgb).dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Normalize a running sum into a CDF, falling back to a uniform distribution if empty.
+fn normalize_cdf(cdf: &mut [f32], sum: f32) {
+    let count = cdf.len() as f32;
+    for (i, value) in cdf.iter_mut().enumerate() {
+        *value = if sum > 0.0 {
+            *value / sum
+        } else {
+            (i + 1) as f32 / count
+        };
+    }
+    if let Some(last) = cdf.last_mut() {
+        *last = 1.0;
+    }
+}
+
+/// Encoding of the probe texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProbeFormat {
+    /// Shared exponent, decoded in the shaders.
+    #[default]
+    Rgbe8,
+    /// Half floats, requires the `FLOAT_PROBE` shading define.
+    Rgba16Float,
+}
+
+impl ProbeFormat {
+    pub fn texture_format(&self) -> wgpu::TextureFormat {
+        match self {
+            Self::Rgbe8 => wgpu::TextureFormat::Rgba8Unorm,
+            Self::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+pub enum EnvironmentMapError {
+    /// File couldn't be read or decoded.
+    Decode(image::ImageError),
+}
+
+#[cfg(feature = "image")]
+impl std::fmt::Debug for EnvironmentMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decode(error) => write!(f, "Decode: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl std::fmt::Display for EnvironmentMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Decode(error) => write!(f, "Failed to decode the environment map: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl std::error::Error for EnvironmentMapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(error) => Some(error),
+        }
+    }
+}
+
+/// Cubemap face, in the `wgpu` layer order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubemapFace {
+    PositiveX = 0,
+    NegativeX = 1,
+    PositiveY = 2,
+    NegativeY = 3,
+    PositiveZ = 4,
+    NegativeZ = 5,
+}
+
+impl CubemapFace {
+    pub const ALL: [CubemapFace; 6] = [
+        Self::PositiveX,
+        Self::NegativeX,
+        Self::PositiveY,
+        Self::NegativeY,
+        Self::PositiveZ,
+        Self::NegativeZ,
+    ];
+
+    /// Direction pointing at `(s, t)`, in `[-1; 1]` on the face.
+    fn direction(&self, s: f32, t: f32) -> Vec3 {
+        match self {
+            Self::PositiveX => Vec3::new(1.0, -t, -s),
+            Self::NegativeX => Vec3::new(-1.0, -t, s),
+            Self::PositiveY => Vec3::new(s, 1.0, t),
+            Self::NegativeY => Vec3::new(s, -1.0, -t),
+            Self::PositiveZ => Vec3::new(s, -t, 1.0),
+            Self::NegativeZ => Vec3::new(-s, -t, -1.0),
+        }
+    }
+
+    /// Face and `(s, t)` coordinates, in `[0; 1]`, hit by `dir`.
+    fn from_direction(dir: Vec3) -> (Self, Vec2) {
+        let abs = dir.abs();
+        let (face, sc, tc, ma) = if abs.x >= abs.y && abs.x >= abs.z {
+            if dir.x > 0.0 {
+                (Self::PositiveX, -dir.z, -dir.y, abs.x)
+            } else {
+                (Self::NegativeX, dir.z, -dir.y, abs.x)
+            }
+        } else if abs.y >= abs.z {
+            if dir.y > 0.0 {
+                (Self::PositiveY, dir.x, dir.z, abs.y)
+            } else {
+                (Self::NegativeY, dir.x, -dir.z, abs.y)
+            }
+        } else if dir.z > 0.0 {
+            (Self::PositiveZ, dir.x, -dir.y, abs.z)
+        } else {
+            (Self::NegativeZ, -dir.x, -dir.y, abs.z)
+        };
+        (face, (Vec2::new(sc, tc) / ma + 1.0) * 0.5)
+    }
+}
+
+/// Equirectangular environment map, in linear radiance.
+///
+/// The mapping matches `cartesianToEqui` in `shading.comp`: the top row
+/// is the `+y` pole.
+pub struct EnvironmentMap {
+    pub width: u32,
+    pub height: u32,
+    /// Texels, stored row by row.
+    pub texels: Vec<[f32; 3]>,
+}
+
+impl EnvironmentMap {
+    pub fn new(width: u32, height: u32, texels: Vec<[f32; 3]>) -> Self {
+        assert_eq!(
+            texels.len(),
+            (width * height) as usize,
+            "texel count must match the map size"
+        );
+        Self {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    pub fn from_rgbe8(width: u32, height: u32, texels: &[[u8; 4]]) -> Self {
+        Self::new(width, height, texels.iter().map(decode_rgbe8).collect())
+    }
+
+    /// Decode a Radiance `.hdr` file.
+    #[cfg(feature = "image")]
+    pub fn from_hdr(bytes: &[u8]) -> Result<Self, EnvironmentMapError> {
+        Self::decode(bytes, image::ImageFormat::Hdr)
+    }
+
+    /// Decode an OpenEXR file.
+    #[cfg(feature = "image")]
+    pub fn from_exr(bytes: &[u8]) -> Result<Self, EnvironmentMapError> {
+        Self::decode(bytes, image::ImageFormat::OpenExr)
+    }
+
+    /// Load a `.hdr` or `.exr` file, based on its extension.
+    #[cfg(feature = "image")]
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, EnvironmentMapError> {
+        let image = image::open(path).map_err(EnvironmentMapError::Decode)?;
+        Ok(Self::from_image(image))
+    }
+
+    #[cfg(feature = "image")]
+    fn decode(bytes: &[u8], format: image::ImageFormat) -> Result<Self, EnvironmentMapError> {
+        let image = image::load_from_memory_with_format(bytes, format)
+            .map_err(EnvironmentMapError::Decode)?;
+        Ok(Self::from_image(image))
+    }
+
+    #[cfg(feature = "image")]
+    fn from_image(image: image::DynamicImage) -> Self {
+        let image = image.into_rgb32f();
+        let (width, height) = image.dimensions();
+        let texels = bytemuck::cast_slice(&image.into_raw()).to_vec();
+        Self::new(width, height, texels)
+    }
+
+    /// Convert a cubemap into an equirectangular map.
+    ///
+    /// `faces` are stored in the [`CubemapFace`] order, each with `face_size * face_size` texels.
+    pub fn from_cubemap(
+        face_size: u32,
+        faces: &[Vec<[f32; 3]>; 6],
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let uv = Vec2::new(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                let (face, st) = CubemapFace::from_direction(equi_to_cartesian(uv));
+                texels.push(sample_bilinear(
+                    &faces[face as usize],
+                    face_size,
+                    face_size,
+                    st,
+                    false,
+                ));
+            }
+        }
+        Self::new(width, height, texels)
+    }
+
+    /// Convert the map into a cubemap, with faces in the [`CubemapFace`] order.
+    pub fn to_cubemap(&self, face_size: u32) -> [Vec<[f32; 3]>; 6] {
+        CubemapFace::ALL.map(|face| {
+            let mut texels = Vec::with_capacity((face_size * face_size) as usize);
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let s = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                    let t = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                    texels.push(self.sample(face.direction(s, t)));
+                }
+            }
+            texels
+        })
+    }
+
+    /// Bilinearly sample the radiance in direction `dir`.
+    pub fn sample(&self, dir: Vec3) -> [f32; 3] {
+        let uv = cartesian_to_equi(dir.normalize());
+        sample_bilinear(&self.texels, self.width, self.height, uv, true)
+    }
+
+    /// Encode the texels in the format expected by the probe texture.
+    pub fn to_rgbe8(&self) -> Vec<[u8; 4]> {
+        self.texels.iter().map(encode_rgbe8).collect()
+    }
+
+    /// Encode the texels as half floats, with an alpha of `1.0`.
+    pub fn to_rgba16f(&self) -> Vec<[u16; 4]> {
+        let one = half::f16::ONE.to_bits();
+        self.texels
+            .iter()
+            .map(|t| {
+                let [r, g, b] = t.map(|v| half::f16::from_f32(v).to_bits());
+                [r, g, b, one]
+            })
+            .collect()
+    }
+
+    pub fn distribution(&self) -> ProbeDistribution {
+        ProbeDistribution::new(self.width, self.height, &self.texels)
+    }
+
+    /// Create and fill the probe texture.
+    ///
+    /// Bound with [`crate::RTSurfaceBindGroupLayout::create_bindgroup`].
+    pub fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: ProbeFormat,
+    ) -> ProbeTexture {
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Probe"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: format.texture_format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let (data, bytes_per_texel): (Vec<u8>, u32) = match format {
+            ProbeFormat::Rgbe8 => (bytemuck::cast_slice(&self.to_rgbe8()).to_vec(), 4),
+            ProbeFormat::Rgba16Float => (bytemuck::cast_slice(&self.to_rgba16f()).to_vec(), 8),
+        };
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                aspect: wgpu::TextureAspect::All,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_texel * self.width),
+                rows_per_image: Some(self.height),
+            },
+            size,
+        );
+
+        ProbeTexture {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture,
+            format,
+        }
+    }
+}
+
+/// Probe texture, created by [`EnvironmentMap::upload`].
+pub struct ProbeTexture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    format: ProbeFormat,
+}
+
+impl ProbeTexture {
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn format(&self) -> ProbeFormat {
+        self.format
+    }
+}
+
+/// Port of `cartesianToEqui` in `shading.comp`.
+fn cartesian_to_equi(dir: Vec3) -> Vec2 {
+    let longitude = dir.z.atan2(dir.x) + std::f32::consts::PI;
+    let latitude = dir.y.clamp(-1.0, 1.0).acos();
+    Vec2::new(longitude, latitude) / Vec2::new(2.0 * std::f32::consts::PI, std::f32::consts::PI)
+}
+
+/// Port of `equiToCartesian` in `shading.comp`.
+fn equi_to_cartesian(uv: Vec2) -> Vec3 {
+    let longitude = uv.x * 2.0 * std::f32::consts::PI - std::f32::consts::PI;
+    let latitude = uv.y * std::f32::consts::PI;
+    Vec3::new(
+        latitude.sin() * longitude.cos(),
+        latitude.cos(),
+        latitude.sin() * longitude.sin(),
+    )
+}
+
+/// Bilinear lookup, with `uv` in `[0; 1]`.
+///
+/// Rows are clamped, and columns wrap around if `wrap` is `true`.
+fn sample_bilinear(texels: &[[f32; 3]], width: u32, height: u32, uv: Vec2, wrap: bool) -> [f32; 3] {
+    let (w, h) = (width as i32, height as i32);
+    let p = uv * Vec2::new(width as f32, height as f32) - 0.5;
+    let (x0, y0) = (p.x.floor() as i32, p.y.floor() as i32);
+    let (fx, fy) = (p.x - x0 as f32, p.y - y0 as f32);
+
+    let fetch = |x: i32, y: i32| -> Vec3 {
+        let x = if wrap {
+            x.rem_euclid(w)
+        } else {
+            x.clamp(0, w - 1)
+        };
+        let y = y.clamp(0, h - 1);
+        Vec3::from(texels[(y * w + x) as usize])
+    };
+    let top = fetch(x0, y0).lerp(fetch(x0 + 1, y0), fx);
+    let bottom = fetch(x0, y0 + 1).lerp(fetch(x0 + 1, y0 + 1), fx);
+    top.lerp(bottom, fy).to_array()
+}
+
+/// Inverse of [`decode_rgbe8`].
+fn encode_rgbe8(rgb: &[f32; 3]) -> [u8; 4] {
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    if max <= 1e-32 {
+        return [0; 4];
+    }
+    // Smallest exponent such that all components fit in `[0; 1]`.
+    let exponent = (max.log2().floor() as i32 + 1).clamp(-128, 127);
+    let scale = 255.0 / (exponent as f32).exp2();
+    let [r, g, b] = rgb.map(|v| (v * scale).round().clamp(0.0, 255.0) as u8);
+    [r, g, b, (exponent + 128) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic texels, in `[0.5; 4.5]`.
+    fn texels(width: u32, height: u32) -> Vec<[f32; 3]> {
+        (0..width * height)
+            .map(|i| {
+                let v = 0.5 + ((i * 7 + i / width * 13) % 5) as f32;
+                [v, v * 0.5, v * 0.25]
+            })
+            .collect()
+    }
+
+    /// Stratified random numbers, in `(0; 1)`.
+    fn strata(count: u32) -> impl Iterator<Item = Vec2> {
+        (0..count * count).map(move |i| {
+            Vec2::new((i % count) as f32 + 0.5, (i / count) as f32 + 0.5) / count as f32
+        })
+    }
+
+    #[test]
+    fn rgbe_round_trip() {
+        let values = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.5, 0.25],
+            [0.001, 0.002, 0.003],
+            [12.5, 3.0, 0.1],
+            [4096.0, 1.0, 0.0],
+        ];
+        for rgb in values {
+            let decoded = decode_rgbe8(&encode_rgbe8(&rgb));
+            // Components share the exponent of the largest one.
+            let max = rgb[0].max(rgb[1]).max(rgb[2]);
+            for (a, b) in rgb.iter().zip(decoded) {
+                assert!(
+                    (a - b).abs() <= max / 128.0,
+                    "{:?} decoded as {:?}",
+                    rgb,
+                    decoded
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn distribution_cdf_is_monotonic() {
+        let (width, height) = (16, 8);
+        let distribution = ProbeDistribution::new(width, height, &texels(width, height));
+        assert_eq!(distribution.cdf.len(), (height + width * height) as usize);
+
+        let (marginal, conditionals) = distribution.cdf.split_at(height as usize);
+        for cdf in std::iter::once(marginal).chain(conditionals.chunks_exact(width as usize)) {
+            assert!(cdf.windows(2).all(|w| w[0] <= w[1]), "{:?}", cdf);
+            assert_eq!(*cdf.last().unwrap(), 1.0);
+        }
+    }
+
+    #[test]
+    fn distribution_pdf_integrates_to_one() {
+        let (width, height) = (16, 8);
+        let distribution = ProbeDistribution::new(width, height, &texels(width, height));
+
+        // Midpoint rule over the sphere, with `dω = 2π² sinθ du dv`.
+        let count = 128;
+        let integral: f32 = strata(count)
+            .map(|uv| {
+                let sin_theta = (uv.y * std::f32::consts::PI).sin();
+                let solid_angle = 2.0 * std::f32::consts::PI * std::f32::consts::PI * sin_theta
+                    / (count * count) as f32;
+                distribution.pdf(equi_to_cartesian(uv)) * solid_angle
+            })
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-3, "integral: {}", integral);
+    }
+
+    #[test]
+    fn distribution_samples_hot_texel() {
+        let (width, height) = (16, 8);
+        let hot = (5, 3);
+        let mut texels = vec![[0.0; 3]; (width * height) as usize];
+        texels[(hot.1 * width + hot.0) as usize] = [10.0, 10.0, 10.0];
+        let distribution = ProbeDistribution::new(width, height, &texels);
+
+        for r in strata(8) {
+            let dir = distribution.sample(r);
+            let uv = cartesian_to_equi(dir) * Vec2::new(width as f32, height as f32);
+            assert_eq!((uv.x as u32, uv.y as u32), hot, "sampled with {:?}", r);
+            assert!(distribution.pdf(dir) > 0.0);
+        }
+    }
+
+    #[test]
+    fn cubemap_faces_round_trip() {
+        for face in CubemapFace::ALL {
+            for st in strata(4) {
+                let dir = face.direction(st.x * 2.0 - 1.0, st.y * 2.0 - 1.0);
+                let (hit, uv) = CubemapFace::from_direction(dir);
+                assert_eq!(hit, face);
+                assert!((uv - st).abs().max_element() < 1e-5, "{:?}: {:?}", face, st);
+            }
+        }
+    }
+
+    #[test]
+    fn equirect_cubemap_round_trip() {
+        // Smooth radiance, so that bilinear filtering has little error.
+        let (width, height) = (64, 32);
+        let texels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let uv = Vec2::new(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                (equi_to_cartesian(uv) * 0.5 + 0.5).to_array()
+            })
+            .collect();
+        let map = EnvironmentMap::new(width, height, texels);
+
+        let faces = map.to_cubemap(32);
+        let result = EnvironmentMap::from_cubemap(32, &faces, width, height);
+        for (expected, actual) in map.texels.iter().zip(&result.texels) {
+            let error = (Vec3::from(*expected) - Vec3::from(*actual))
+                .abs()
+                .max_element();
+            assert!(error < 0.05, "{:?} converted to {:?}", expected, actual);
+        }
+    }
+}
@@ -11,6 +11,8 @@ impl RTGeometryBindGroupLayout {
     const TRIANGLES_BINDING: u32 = 2;
     const VERTEX_BINDING: u32 = 3;
     const LIGHT_BINDING: u32 = 4;
+    const TLAS_BINDING: u32 = 5;
+    const PROCEDURAL_BINDING: u32 = 6;
 
     pub fn new(device: &wgpu::Device) -> Self {
         let inner = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -66,11 +68,32 @@ impl RTGeometryBindGroupLayout {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::TLAS_BINDING,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::PROCEDURAL_BINDING,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
         Self { 0: inner }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_bindgroup(
         &self,
         device: &wgpu::Device,
@@ -79,6 +102,8 @@ impl RTGeometryBindGroupLayout {
         triangles: gpu::StorageBufferSlice<uniforms::BVHPrimitive>,
         vertices: gpu::StorageBufferSlice<uniforms::Vertex>,
         lights: gpu::StorageBufferSlice<uniforms::Light>,
+        tlas: gpu::StorageBufferSlice<uniforms::TLASNode>,
+        procedurals: gpu::StorageBufferSlice<uniforms::ProceduralPrimitive>,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Geometry Bind Group"),
@@ -104,6 +129,14 @@ impl RTGeometryBindGroupLayout {
                     binding: Self::LIGHT_BINDING,
                     resource: lights.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: Self::TLAS_BINDING,
+                    resource: tlas.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::PROCEDURAL_BINDING,
+                    resource: procedurals.as_entire_binding(),
+                },
             ],
         })
     }
@@ -120,6 +153,7 @@ impl RTSurfaceBindGroupLayout {
     const SAMPLER_LINEAR_BINDING: u32 = 5;
     const TEXTURE_NOISE_BINDING: u32 = 6;
     const PARAMETERS_BINDING: u32 = 7;
+    const PROBE_DISTRIBUTION_BINDING: u32 = 8;
 
     pub fn new(device: &wgpu::Device) -> Self {
         let inner = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -197,11 +231,26 @@ impl RTSurfaceBindGroupLayout {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::PROBE_DISTRIBUTION_BINDING,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
         Self { 0: inner }
     }
 
+    /// Create the bind group.
+    ///
+    /// `probe_distribution` holds the [`crate::ProbeDistribution::cdf`] of the probe.
+    /// It's only read when sampling the probe, and can otherwise hold a single value.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_bindgroup(
         &self,
         device: &wgpu::Device,
@@ -213,6 +262,7 @@ impl RTSurfaceBindGroupLayout {
         sampler_linear: &wgpu::Sampler,
         texture_noise: &wgpu::TextureView,
         parameters: gpu::UniformBufferSlice<uniforms::RadianceParameters>,
+        probe_distribution: gpu::StorageBufferSlice<f32>,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Surface Bind Group"),
@@ -250,6 +300,10 @@ impl RTSurfaceBindGroupLayout {
                     binding: Self::PARAMETERS_BINDING,
                     resource: parameters.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: Self::PROBE_DISTRIBUTION_BINDING,
+                    resource: probe_distribution.as_entire_binding(),
+                },
             ],
         })
     }
@@ -2,15 +2,26 @@
 compile_error!("only the emscripten target supports the feature \"tinybvh\"");
 
 pub mod blas;
+pub mod blas_cache;
+pub mod emissive;
+pub mod environment;
 pub mod layouts;
 pub mod macros;
 pub mod passes;
 pub mod shaders;
+pub mod tangents;
+pub mod tlas;
+pub mod traversal;
 pub mod uniforms;
 
 pub use blas::*;
+pub use blas_cache::*;
+pub use environment::*;
 pub use layouts::*;
 pub use shaders::*;
+pub use tangents::*;
+pub use tlas::*;
+pub use traversal::*;
 pub use uniforms::*;
 
 pub fn get_dispatch_size(
@@ -1,10 +1,11 @@
 use std::borrow::Cow;
 
 use albedo_backend::data::ShaderCache;
-use albedo_backend::gpu::ComputePipeline;
+use albedo_backend::gpu::{self, ComputePipeline};
 
 use crate::get_dispatch_size;
 use crate::macros::path_separator;
+use crate::uniforms;
 
 use super::GBUFFER_READ_TY;
 
@@ -23,6 +24,7 @@ impl ATrousPass {
     const RADIANCE_BINDING: u32 = 1;
     const RADIANCE_OUT_BINDING: u32 = 2;
     const SAMPLER_BINDING: u32 = 3;
+    const PARAMETERS_BINDING: u32 = 4;
 
     pub fn new(device: &wgpu::Device, processor: &ShaderCache) -> Self {
         let frame_bind_group_layout =
@@ -61,6 +63,16 @@ impl ATrousPass {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::PARAMETERS_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
@@ -117,6 +129,7 @@ impl ATrousPass {
         gbuffer: &wgpu::TextureView,
         radiance: &wgpu::TextureView,
         sampler: &wgpu::Sampler,
+        parameters: gpu::UniformBufferSlice<uniforms::SvgfParameters>,
     ) -> [wgpu::BindGroup; 2] {
         [
             // TODO: Probably cleaner to use 2 bind groups here
@@ -140,6 +153,10 @@ impl ATrousPass {
                         binding: Self::SAMPLER_BINDING,
                         resource: wgpu::BindingResource::Sampler(sampler),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: Self::PARAMETERS_BINDING,
+                        resource: parameters.as_entire_binding(),
+                    },
                 ],
             }),
             device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -162,11 +179,22 @@ impl ATrousPass {
                         binding: Self::SAMPLER_BINDING,
                         resource: wgpu::BindingResource::Sampler(sampler),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: Self::PARAMETERS_BINDING,
+                        resource: parameters.as_entire_binding(),
+                    },
                 ],
             }),
         ]
     }
 
+    /// Number of filter iterations.
+    ///
+    /// The result ends up in `radiance` for an even count, and in `out_radiance` otherwise.
+    pub fn count(&self) -> u8 {
+        self.count
+    }
+
     pub fn dispatch(
         &self,
         encoder: &mut wgpu::CommandEncoder,
@@ -0,0 +1,235 @@
+use bitflags::bitflags;
+use wgpu::naga::FastHashMap;
+
+bitflags! {
+    /// Arbitrary output variables written by the shading pass.
+    ///
+    /// Geometry outputs are written for the primary hit only. Pixels without
+    /// hit have a zero albedo, normal, depth and position, and an `u32::MAX` id.
+    ///
+    /// Each output uses one storage texture: enabling several of them might
+    /// require raising `max_storage_textures_per_shader_stage`.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct AovFlags: u32 {
+        /// Linear albedo, `Rgba32Float`.
+        const ALBEDO = 0b00000001;
+        /// World space shading normal, facing the ray, `Rgba32Float`.
+        const NORMAL = 0b00000010;
+        /// Distance along the primary ray, `R32Float`.
+        const DEPTH = 0b00000100;
+        /// World space position, `Rgba32Float`.
+        const POSITION = 0b00001000;
+        /// Index of the instance, `R32Uint`.
+        const INSTANCE_ID = 0b00010000;
+        /// Index of the material, `R32Uint`.
+        const MATERIAL_ID = 0b00100000;
+        /// Radiance gathered at each bounce, one layer per bounce, `Rgba32Float`.
+        ///
+        /// With next event estimation, direct lighting is added to the layer
+        /// of its bounce by [`super::ShadowPass`]. With the denoiser, bounces
+        /// following the primary hit are demodulated by the reflectance of its lobes.
+        const BOUNCE_RADIANCE = 0b01000000;
+    }
+}
+
+impl AovFlags {
+    /// Define, binding and format of each output.
+    const OUTPUTS: [(AovFlags, &'static str, u32, wgpu::TextureFormat); 7] = [
+        (
+            AovFlags::ALBEDO,
+            "AOV_ALBEDO",
+            8,
+            wgpu::TextureFormat::Rgba32Float,
+        ),
+        (
+            AovFlags::NORMAL,
+            "AOV_NORMAL",
+            9,
+            wgpu::TextureFormat::Rgba32Float,
+        ),
+        (
+            AovFlags::DEPTH,
+            "AOV_DEPTH",
+            10,
+            wgpu::TextureFormat::R32Float,
+        ),
+        (
+            AovFlags::POSITION,
+            "AOV_POSITION",
+            11,
+            wgpu::TextureFormat::Rgba32Float,
+        ),
+        (
+            AovFlags::INSTANCE_ID,
+            "AOV_INSTANCE_ID",
+            12,
+            wgpu::TextureFormat::R32Uint,
+        ),
+        (
+            AovFlags::MATERIAL_ID,
+            "AOV_MATERIAL_ID",
+            13,
+            wgpu::TextureFormat::R32Uint,
+        ),
+        (
+            AovFlags::BOUNCE_RADIANCE,
+            "AOV_BOUNCE_RADIANCE",
+            14,
+            wgpu::TextureFormat::Rgba32Float,
+        ),
+    ];
+
+    /// Add the shader defines of the outputs to `defines`.
+    ///
+    /// Used to create a [`super::ShadingPass`] or a [`super::PathTracer`] writing the outputs.
+    pub fn insert_defines(self, defines: &mut FastHashMap<String, String>) {
+        for (flag, define, _, _) in Self::OUTPUTS.iter().copied() {
+            if self.contains(flag) {
+                defines.insert(define.into(), "".into());
+            }
+        }
+    }
+
+    pub(crate) fn from_defines(defines: &FastHashMap<String, String>) -> Self {
+        let mut flags = AovFlags::empty();
+        for (flag, define, _, _) in Self::OUTPUTS.iter().copied() {
+            if defines.contains_key(define) {
+                flags |= flag;
+            }
+        }
+        flags
+    }
+
+    /// Texture format of a single output.
+    pub fn format(self) -> wgpu::TextureFormat {
+        let Some((_, _, _, format)) = Self::OUTPUTS.iter().find(|(flag, ..)| *flag == self) else {
+            panic!("expected a single output, got {:?}", self)
+        };
+        *format
+    }
+
+    fn view_dimension(self) -> wgpu::TextureViewDimension {
+        if self == AovFlags::BOUNCE_RADIANCE {
+            wgpu::TextureViewDimension::D2Array
+        } else {
+            wgpu::TextureViewDimension::D2
+        }
+    }
+
+    /// Binding and layout entry of each output, in the shading frame bind group.
+    pub(crate) fn layout_entries(self) -> impl Iterator<Item = wgpu::BindGroupLayoutEntry> {
+        Self::OUTPUTS
+            .iter()
+            .copied()
+            .filter(move |(flag, ..)| self.contains(*flag))
+            .map(|(flag, _, binding, format)| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    format,
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    view_dimension: flag.view_dimension(),
+                },
+                count: None,
+            })
+    }
+}
+
+struct AovTarget {
+    flag: AovFlags,
+    binding: u32,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+/// Textures of the outputs enabled in [`AovFlags`].
+///
+/// Textures can be copied, e.g., using [`albedo_backend::gpu::TextureReadback`].
+pub struct AovTargets {
+    flags: AovFlags,
+    targets: Vec<AovTarget>,
+}
+
+impl AovTargets {
+    /// Create one texture per output in `flags`.
+    ///
+    /// `bounces` is the number of layers of [`AovFlags::BOUNCE_RADIANCE`],
+    /// usually [`super::PathDepth::max_bounces`].
+    pub fn new(device: &wgpu::Device, size: (u32, u32), flags: AovFlags, bounces: u32) -> Self {
+        let targets = AovFlags::OUTPUTS
+            .iter()
+            .copied()
+            .filter(|(flag, ..)| flags.contains(*flag))
+            .map(|(flag, define, binding, format)| {
+                let layers = if flag == AovFlags::BOUNCE_RADIANCE {
+                    bounces.max(1)
+                } else {
+                    1
+                };
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(define),
+                    size: wgpu::Extent3d {
+                        width: size.0,
+                        height: size.1,
+                        depth_or_array_layers: layers,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::STORAGE_BINDING
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(flag.view_dimension()),
+                    ..Default::default()
+                });
+                AovTarget {
+                    flag,
+                    binding,
+                    texture,
+                    view,
+                }
+            })
+            .collect();
+        Self { flags, targets }
+    }
+
+    pub fn flags(&self) -> AovFlags {
+        self.flags
+    }
+
+    /// Texture of a single output, `None` if it isn't enabled.
+    pub fn texture(&self, aov: AovFlags) -> Option<&wgpu::Texture> {
+        self.find(aov).map(|target| &target.texture)
+    }
+
+    /// View of a single output, `None` if it isn't enabled.
+    pub fn view(&self, aov: AovFlags) -> Option<&wgpu::TextureView> {
+        self.find(aov).map(|target| &target.view)
+    }
+
+    /// Bind group entries of the outputs in `flags`.
+    pub(crate) fn entries(
+        &self,
+        flags: AovFlags,
+    ) -> impl Iterator<Item = wgpu::BindGroupEntry<'_>> {
+        let missing = flags.difference(self.flags);
+        if !missing.is_empty() {
+            panic!("missing AOV targets: {:?}", missing)
+        }
+        self.targets
+            .iter()
+            .filter(move |target| flags.contains(target.flag))
+            .map(|target| wgpu::BindGroupEntry {
+                binding: target.binding,
+                resource: wgpu::BindingResource::TextureView(&target.view),
+            })
+    }
+
+    fn find(&self, aov: AovFlags) -> Option<&AovTarget> {
+        self.targets.iter().find(|target| target.flag == aov)
+    }
+}
@@ -0,0 +1,213 @@
+use std::borrow::Cow;
+
+use albedo_backend::gpu;
+
+use crate::get_dispatch_size;
+use crate::macros::path_separator;
+use crate::uniforms;
+
+/// Dense list of the active rays, and the matching indirect dispatch size.
+///
+/// Filled by [`RayCompactionPass`], and consumed by the passes created
+/// with compaction, e.g., [`super::IntersectorPass::new_compacted`].
+pub struct CompactedRays {
+    active_rays: gpu::Buffer<u32>,
+    dispatch_args: gpu::Buffer<u32>,
+}
+
+impl CompactedRays {
+    /// Size of the header preceding the ray indices, in `u32`.
+    const HEADER_SIZE: u64 = 4;
+
+    pub fn new(device: &wgpu::Device, ray_count: u64) -> Self {
+        Self {
+            active_rays: gpu::Buffer::new_storage(
+                device,
+                Self::HEADER_SIZE + ray_count,
+                Some(gpu::BufferInitDescriptor::new(
+                    Some("Active Rays Buffer"),
+                    wgpu::BufferUsages::COPY_SRC,
+                )),
+            ),
+            dispatch_args: gpu::Buffer::new_storage(
+                device,
+                3,
+                Some(gpu::BufferInitDescriptor::new(
+                    Some("Active Rays Dispatch Buffer"),
+                    wgpu::BufferUsages::INDIRECT,
+                )),
+            ),
+        }
+    }
+
+    /// Maximum number of rays that can be compacted.
+    pub fn capacity(&self) -> u64 {
+        self.active_rays.count() - Self::HEADER_SIZE
+    }
+
+    /// Number of active rays, followed by the ray indices.
+    pub fn active_rays(&self) -> gpu::StorageBufferSlice<'_, u32> {
+        self.active_rays.as_storage_slice().unwrap()
+    }
+
+    /// Workgroup count, to use with `dispatch_workgroups_indirect`.
+    pub fn dispatch_args(&self) -> &wgpu::Buffer {
+        self.dispatch_args.inner()
+    }
+
+    /// Copy the number of active rays into the `u32` of `buffer` at index `index`.
+    pub fn copy_count(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        index: u64,
+    ) {
+        let size = std::mem::size_of::<u32>() as u64;
+        encoder.copy_buffer_to_buffer(self.active_rays.inner(), 0, buffer, index * size, size);
+    }
+}
+
+/// Stream compaction of the rays that aren't terminated.
+///
+/// Following passes only process the active rays, with one invocation per ray.
+pub struct RayCompactionPass {
+    frame_bind_group_layout: wgpu::BindGroupLayout,
+    compact_pipeline: wgpu::ComputePipeline,
+    dispatch_size_pipeline: wgpu::ComputePipeline,
+}
+
+impl RayCompactionPass {
+    const RAY_BINDING: u32 = 0;
+    const ACTIVE_RAY_BINDING: u32 = 1;
+    const DISPATCH_ARGS_BINDING: u32 = 2;
+
+    const WORKGROUP_SIZE: (u32, u32, u32) = (8, 8, 1);
+
+    /// Create the pass.
+    ///
+    /// At the opposite of the other passes, `source` is WGSL, since the
+    /// GLSL frontend of naga doesn't support atomics.
+    pub fn new(device: &wgpu::Device, source: Option<&str>) -> Self {
+        let storage = |read_only| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let frame_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Ray Compaction Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::RAY_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: storage(true),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::ACTIVE_RAY_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: storage(false),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::DISPATCH_ARGS_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: storage(false),
+                        count: None,
+                    },
+                ],
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ray Compaction Pipeline Layout"),
+            bind_group_layouts: &[&frame_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Ray Compaction Shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source.unwrap_or(include_str!(
+                concat!(
+                    "..",
+                    path_separator!(),
+                    "..",
+                    path_separator!(),
+                    "shaders",
+                    path_separator!(),
+                    "compaction.wgsl"
+                )
+            )))),
+        });
+
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Ray Compaction Pipeline"),
+                layout: Some(&pipeline_layout),
+                entry_point: Some(entry_point),
+                module: &shader,
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            compact_pipeline: create_pipeline("compact"),
+            dispatch_size_pipeline: create_pipeline("dispatch_size"),
+            frame_bind_group_layout,
+        }
+    }
+
+    pub fn create_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        rays: gpu::StorageBufferSlice<uniforms::Ray>,
+        compacted: &CompactedRays,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ray Compaction Frame Bind Group"),
+            layout: &self.frame_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: Self::RAY_BINDING,
+                    resource: rays.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::ACTIVE_RAY_BINDING,
+                    resource: compacted.active_rays.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::DISPATCH_ARGS_BINDING,
+                    resource: compacted.dispatch_args.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Gather the active rays in `compacted`.
+    ///
+    /// `size` must match the size used to generate the rays.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_bind_group: &wgpu::BindGroup,
+        compacted: &CompactedRays,
+        size: (u32, u32, u32),
+    ) {
+        encoder.clear_buffer(
+            compacted.active_rays.inner(),
+            0,
+            Some(CompactedRays::HEADER_SIZE * std::mem::size_of::<u32>() as u64),
+        );
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Ray Compaction Pass"),
+            timestamp_writes: None,
+        });
+        let workgroups = get_dispatch_size(&size, &Self::WORKGROUP_SIZE);
+        pass.set_bind_group(0, frame_bind_group, &[]);
+        pass.set_pipeline(&self.compact_pipeline);
+        pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+        pass.set_pipeline(&self.dispatch_size_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+}
@@ -8,6 +8,7 @@ use crate::macros::path_separator;
 use super::super::GBUFFER_READ_TY;
 
 pub struct CompositingPass {
+    frame_bind_group_layout: wgpu::BindGroupLayout,
     pipeline: wgpu::ComputePipeline,
 }
 
@@ -19,6 +20,8 @@ impl CompositingPass {
     const RADIANCE_BINDING: u32 = 1;
     const RADIANCE_OUT_BINDING: u32 = 2;
     const SAMPLER_BINDING: u32 = 3;
+    const SPECULAR_BINDING: u32 = 4;
+    const REFLECTANCE_BINDING: u32 = 5;
 
     pub fn new_inlined(device: &wgpu::Device, processor: &ShaderCache) -> Self {
         Self::new_raw(
@@ -87,6 +90,22 @@ impl CompositingPass {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::SPECULAR_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::REFLECTANCE_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: GBUFFER_READ_TY,
+                        count: None,
+                    },
                 ],
             });
 
@@ -113,7 +132,53 @@ impl CompositingPass {
             cache: None,
         });
 
-        Ok(Self { pipeline })
+        Ok(Self {
+            frame_bind_group_layout,
+            pipeline,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        out_radiance: &wgpu::TextureView,
+        gbuffer: &wgpu::TextureView,
+        diffuse: &wgpu::TextureView,
+        specular: &wgpu::TextureView,
+        reflectance: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compositing Frame Bind Group"),
+            layout: &self.frame_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: Self::GBUFFER_BINDING,
+                    resource: wgpu::BindingResource::TextureView(gbuffer),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::RADIANCE_BINDING,
+                    resource: wgpu::BindingResource::TextureView(diffuse),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::RADIANCE_OUT_BINDING,
+                    resource: wgpu::BindingResource::TextureView(out_radiance),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::SAMPLER_BINDING,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::SPECULAR_BINDING,
+                    resource: wgpu::BindingResource::TextureView(specular),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::REFLECTANCE_BINDING,
+                    resource: wgpu::BindingResource::TextureView(reflectance),
+                },
+            ],
+        })
     }
 
     pub fn dispatch(
@@ -1,3 +1,7 @@
 mod composite;
+mod svgf;
+mod variance;
 
 pub use composite::*;
+pub use svgf::*;
+pub use variance::*;
@@ -0,0 +1,375 @@
+use albedo_backend::data::ShaderCache;
+use albedo_backend::gpu;
+
+use crate::get_dispatch_size;
+use crate::passes::{ATrousPass, DenoiseSignal, TemporalAccumulationPass};
+use crate::uniforms::{Ray, SvgfParameters};
+use crate::DenoiseResources;
+
+use super::{CompositingPass, VarianceEstimationPass};
+
+struct Target {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl Target {
+    fn new(
+        device: &wgpu::Device,
+        label: &str,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: usage
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+}
+
+/// History and intermediate textures of one signal.
+struct SignalTargets {
+    integrated: Target,
+    filtered: [Target; 2],
+    retain: Target,
+    moments: [Target; 2],
+    history: [gpu::Buffer<u32>; 2],
+}
+
+impl SignalTargets {
+    fn new(device: &wgpu::Device, label: &str, size: (u32, u32), history_count: u64) -> Self {
+        let radiance = |name| {
+            Target::new(
+                device,
+                &format!("SVGF {} {}", label, name),
+                size,
+                wgpu::TextureFormat::Rgba32Float,
+                wgpu::TextureUsages::COPY_SRC,
+            )
+        };
+        let moments = |name| {
+            Target::new(
+                device,
+                &format!("SVGF {} {}", label, name),
+                size,
+                wgpu::TextureFormat::Rg32Float,
+                wgpu::TextureUsages::empty(),
+            )
+        };
+        let history = |name| {
+            gpu::Buffer::new_storage(
+                device,
+                history_count,
+                Some(gpu::BufferInitDescriptor::with_label(Some(&format!(
+                    "SVGF {} {}",
+                    label, name
+                )))),
+            )
+        };
+        Self {
+            integrated: radiance("Integrated Radiance"),
+            filtered: [
+                radiance("Filtered Radiance 0"),
+                radiance("Filtered Radiance 1"),
+            ],
+            retain: Target::new(
+                device,
+                &format!("SVGF {} Radiance History", label),
+                size,
+                wgpu::TextureFormat::Rgba32Float,
+                wgpu::TextureUsages::COPY_DST,
+            ),
+            moments: [moments("Moments 0"), moments("Moments 1")],
+            history: [history("History 0"), history("History 1")],
+        }
+    }
+}
+
+struct SignalBindGroups {
+    temporal: [wgpu::BindGroup; 2],
+    variance: [wgpu::BindGroup; 2],
+    atrous: [wgpu::BindGroup; 2],
+}
+
+/// Bind groups of [`SvgfDenoiser`], for one pair of current and previous gbuffers.
+struct SvgfBindGroups {
+    diffuse: SignalBindGroups,
+    specular: SignalBindGroups,
+    compositing: wgpu::BindGroup,
+}
+
+/// Resources a set of [`SvgfBindGroups`] was created for.
+#[derive(PartialEq, Eq)]
+struct FrameKey {
+    rays: wgpu::Buffer,
+    gbuffer_current: wgpu::TextureView,
+    gbuffer_previous: wgpu::TextureView,
+    motion: wgpu::TextureView,
+    reflectance: wgpu::TextureView,
+}
+
+impl FrameKey {
+    fn new(rays: &gpu::StorageBufferSlice<Ray>, resources: &DenoiseResources) -> Self {
+        Self {
+            rays: rays.inner().clone(),
+            gbuffer_current: resources.gbuffer_current.clone(),
+            gbuffer_previous: resources.gbuffer_previous.clone(),
+            motion: resources.motion.clone(),
+            reflectance: resources.reflectance.clone(),
+        }
+    }
+}
+
+/// Spatiotemporal variance-guided filtering.
+///
+/// Owns the history, moments and intermediate radiance textures, and runs
+/// the temporal accumulation, variance estimation, à-trous and compositing
+/// passes in a single [`SvgfDenoiser::denoise`] call.
+///
+/// The diffuse and specular signals are filtered independently, and
+/// recombined with their reflectance during compositing.
+///
+/// Bind groups are created on first use and cached for the last two sets of
+/// resources, i.e., for both [`DenoiseResources`] and [`DenoiseResources::pong`]
+/// when gbuffers are swapped every frame.
+pub struct SvgfDenoiser {
+    temporal: TemporalAccumulationPass,
+    variance: VarianceEstimationPass,
+    atrous: ATrousPass,
+    compositing: CompositingPass,
+
+    parameters: SvgfParameters,
+    parameters_buffer: gpu::Buffer<SvgfParameters>,
+    sampler: wgpu::Sampler,
+
+    diffuse: SignalTargets,
+    specular: SignalTargets,
+    output: Target,
+
+    bind_groups: Vec<(FrameKey, SvgfBindGroups)>,
+    size: (u32, u32, u32),
+    frame: usize,
+}
+
+impl SvgfDenoiser {
+    const WORKGROUP_SIZE: (u32, u32, u32) = (8, 8, 1);
+    const CACHED_FRAMES: usize = 2;
+
+    pub fn new(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        size: (u32, u32),
+        parameters: SvgfParameters,
+    ) -> Self {
+        // History is indexed with the dispatch grid, which can be larger than the image.
+        let workgroups = get_dispatch_size(&(size.0, size.1, 1), &Self::WORKGROUP_SIZE);
+        let history_count = (workgroups.0 * workgroups.1 * 64) as u64;
+
+        Self {
+            temporal: TemporalAccumulationPass::new_inlined(device, processor),
+            variance: VarianceEstimationPass::new_inlined(device, processor),
+            atrous: ATrousPass::new(device, processor),
+            compositing: CompositingPass::new_inlined(device, processor),
+            parameters,
+            parameters_buffer: gpu::Buffer::new_with_data(
+                device,
+                &[parameters],
+                Some(gpu::BufferInitDescriptor::new(
+                    Some("SVGF Parameters Buffer"),
+                    wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                )),
+            ),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("SVGF Sampler"),
+                ..Default::default()
+            }),
+            diffuse: SignalTargets::new(device, "Diffuse", size, history_count),
+            specular: SignalTargets::new(device, "Specular", size, history_count),
+            output: Target::new(
+                device,
+                "SVGF Output",
+                size,
+                wgpu::TextureFormat::Rgba32Float,
+                wgpu::TextureUsages::COPY_SRC,
+            ),
+            bind_groups: Vec::with_capacity(Self::CACHED_FRAMES),
+            size: (size.0, size.1, 1),
+            frame: 0,
+        }
+    }
+
+    pub fn parameters(&self) -> &SvgfParameters {
+        &self.parameters
+    }
+
+    pub fn set_parameters(&mut self, queue: &wgpu::Queue, parameters: SvgfParameters) {
+        self.parameters = parameters;
+        self.parameters_buffer.update(queue, &[parameters]);
+    }
+
+    /// Denoised radiance, with both signals modulated by their reflectance.
+    pub fn output(&self) -> &wgpu::TextureView {
+        &self.output.view
+    }
+
+    pub fn output_texture(&self) -> &wgpu::Texture {
+        &self.output.texture
+    }
+
+    /// Denoise the radiance of the current frame into [`SvgfDenoiser::output`].
+    ///
+    /// `rays` holds the noisy radiance, and must match the size of the denoiser.
+    pub fn denoise(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        rays: gpu::StorageBufferSlice<Ray>,
+        resources: &DenoiseResources,
+    ) {
+        let key = FrameKey::new(&rays, resources);
+        let index = match self.bind_groups.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                if self.bind_groups.len() == Self::CACHED_FRAMES {
+                    self.bind_groups.remove(0);
+                }
+                let bind_groups = self.create_frame_bind_groups(device, rays, resources);
+                self.bind_groups.push((key, bind_groups));
+                self.bind_groups.len() - 1
+            }
+        };
+        let bind_groups = &self.bind_groups[index].1;
+        self.denoise_signal(
+            encoder,
+            &self.diffuse,
+            &bind_groups.diffuse,
+            DenoiseSignal::Diffuse,
+        );
+        self.denoise_signal(
+            encoder,
+            &self.specular,
+            &bind_groups.specular,
+            DenoiseSignal::Specular,
+        );
+        self.compositing
+            .dispatch(encoder, &bind_groups.compositing, &self.size);
+        self.frame = 1 - self.frame;
+    }
+
+    fn create_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        rays: gpu::StorageBufferSlice<Ray>,
+        resources: &DenoiseResources,
+    ) -> SvgfBindGroups {
+        SvgfBindGroups {
+            diffuse: self.create_signal_bind_groups(device, &self.diffuse, rays, resources),
+            specular: self.create_signal_bind_groups(device, &self.specular, rays, resources),
+            compositing: self.compositing.create_frame_bind_groups(
+                device,
+                &self.output.view,
+                resources.gbuffer_current,
+                &self.filtered(&self.diffuse).view,
+                &self.filtered(&self.specular).view,
+                resources.reflectance,
+                &self.sampler,
+            ),
+        }
+    }
+
+    /// Texture holding the result of the à-trous passes, which ping-pong
+    /// between both filtered textures.
+    fn filtered<'a>(&self, targets: &'a SignalTargets) -> &'a Target {
+        &targets.filtered[(self.atrous.count() % 2) as usize]
+    }
+
+    fn create_signal_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        targets: &SignalTargets,
+        rays: gpu::StorageBufferSlice<Ray>,
+        resources: &DenoiseResources,
+    ) -> SignalBindGroups {
+        let parameters = self.parameters_buffer.as_uniform_slice().unwrap();
+        let temporal = |i: usize| {
+            self.temporal.create_frame_bind_groups(
+                device,
+                &targets.integrated.view,
+                &targets.moments[i].view,
+                &targets.history[i],
+                &rays,
+                resources.gbuffer_previous,
+                resources.gbuffer_current,
+                resources.motion,
+                &targets.retain.view,
+                &self.sampler,
+                &targets.history[1 - i],
+                &targets.moments[1 - i].view,
+                parameters,
+            )
+        };
+        let variance = |i: usize| {
+            self.variance.create_frame_bind_groups(
+                device,
+                &targets.filtered[0].view,
+                resources.gbuffer_current,
+                &targets.integrated.view,
+                &targets.moments[i].view,
+                &targets.history[i],
+                &self.sampler,
+                parameters,
+            )
+        };
+        SignalBindGroups {
+            temporal: [temporal(0), temporal(1)],
+            variance: [variance(0), variance(1)],
+            atrous: self.atrous.create_frame_bind_groups(
+                device,
+                &targets.filtered[1].view,
+                resources.gbuffer_current,
+                &targets.filtered[0].view,
+                &self.sampler,
+                parameters,
+            ),
+        }
+    }
+
+    fn denoise_signal(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &SignalTargets,
+        bind_groups: &SignalBindGroups,
+        signal: DenoiseSignal,
+    ) {
+        self.temporal.dispatch(
+            encoder,
+            &bind_groups.temporal[self.frame],
+            &self.size,
+            signal,
+        );
+        self.variance
+            .dispatch(encoder, &bind_groups.variance[self.frame], &self.size);
+        self.atrous.dispatch(
+            encoder,
+            &bind_groups.atrous,
+            &targets.filtered[1].texture,
+            &targets.retain.texture,
+            &self.size,
+        );
+    }
+}
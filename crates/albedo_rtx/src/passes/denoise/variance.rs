@@ -0,0 +1,223 @@
+use std::borrow::Cow;
+
+use albedo_backend::data::{CompileError, PreprocessError, ShaderCache};
+use albedo_backend::gpu;
+
+use crate::get_dispatch_size;
+use crate::macros::path_separator;
+use crate::uniforms;
+
+use super::super::GBUFFER_READ_TY;
+
+/// Spatial variance estimation, for pixels with a short history.
+///
+/// The temporal variance is unreliable until enough frames are accumulated.
+/// For such pixels, the variance is instead computed from the moments
+/// of the neighborhood. Other pixels are copied as is.
+pub struct VarianceEstimationPass {
+    frame_bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl VarianceEstimationPass {
+    const WORKGROUP_SIZE: (u32, u32, u32) = (8, 8, 1);
+    const SHADER_ID: &'static str = "variance-estimation.comp";
+
+    const GBUFFER_BINDING: u32 = 0;
+    const RADIANCE_BINDING: u32 = 1;
+    const MOMENTS_BINDING: u32 = 2;
+    const HISTORY_BINDING: u32 = 3;
+    const RADIANCE_OUT_BINDING: u32 = 4;
+    const SAMPLER_BINDING: u32 = 5;
+    const PARAMETERS_BINDING: u32 = 6;
+
+    pub fn new_inlined(device: &wgpu::Device, processor: &ShaderCache) -> Self {
+        Self::new_raw(
+            device,
+            processor,
+            include_str!(concat!(
+                "..",
+                path_separator!(),
+                "..",
+                path_separator!(),
+                "..",
+                path_separator!(),
+                "shaders",
+                path_separator!(),
+                "variance-estimation.comp"
+            )),
+        )
+        .unwrap()
+    }
+
+    pub fn new(device: &wgpu::Device, processor: &ShaderCache) -> Result<Self, CompileError> {
+        let Some(source) = processor.get(Self::SHADER_ID) else {
+            return Err(PreprocessError::Missing(Self::SHADER_ID.to_string()).into());
+        };
+        Self::new_raw(device, processor, source)
+    }
+
+    fn new_raw(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        src: &str,
+    ) -> Result<Self, CompileError> {
+        let texture_ty = wgpu::BindingType::Texture {
+            multisampled: false,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+        };
+        let frame_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Variance Estimation Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::GBUFFER_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: GBUFFER_READ_TY,
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::RADIANCE_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: texture_ty,
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::MOMENTS_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: texture_ty,
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::HISTORY_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::RADIANCE_OUT_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            format: wgpu::TextureFormat::Rgba32Float,
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::SAMPLER_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::PARAMETERS_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let pipeline_layout: wgpu::PipelineLayout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Variance Estimation Pipeline Layout"),
+                bind_group_layouts: &[&frame_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let module = processor.compile_compute(src, None)?;
+        let shader: wgpu::ShaderModule =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Variance Estimation Shader"),
+                source: wgpu::ShaderSource::Naga(Cow::Owned(module)),
+            });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Variance Estimation Pipeline"),
+            layout: Some(&pipeline_layout),
+            entry_point: Some("main"),
+            module: &shader,
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Ok(Self {
+            frame_bind_group_layout,
+            pipeline,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        out_radiance: &wgpu::TextureView,
+        gbuffer: &wgpu::TextureView,
+        radiance: &wgpu::TextureView,
+        moments: &wgpu::TextureView,
+        history: &gpu::Buffer<u32>,
+        sampler: &wgpu::Sampler,
+        parameters: gpu::UniformBufferSlice<uniforms::SvgfParameters>,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Variance Estimation Frame Bind Group"),
+            layout: &self.frame_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: Self::GBUFFER_BINDING,
+                    resource: wgpu::BindingResource::TextureView(gbuffer),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::RADIANCE_BINDING,
+                    resource: wgpu::BindingResource::TextureView(radiance),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::MOMENTS_BINDING,
+                    resource: wgpu::BindingResource::TextureView(moments),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::HISTORY_BINDING,
+                    resource: history.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::RADIANCE_OUT_BINDING,
+                    resource: wgpu::BindingResource::TextureView(out_radiance),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::SAMPLER_BINDING,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::PARAMETERS_BINDING,
+                    resource: parameters.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_bind_group: &wgpu::BindGroup,
+        size: &(u32, u32, u32),
+    ) {
+        let workgroups = get_dispatch_size(size, &Self::WORKGROUP_SIZE);
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Variance Estimation Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, frame_bind_group, &[]);
+        pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+    }
+}
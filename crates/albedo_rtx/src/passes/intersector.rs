@@ -1,10 +1,13 @@
 use std::borrow::Cow;
 
 use albedo_backend::{data::ShaderCache, gpu};
+use wgpu::naga::FastHashMap;
 use wgpu::ShaderModuleDescriptor;
 
 use crate::macros::path_separator;
-use crate::uniforms;
+use crate::uniforms::{self, VisibilityMask};
+
+use super::CompactedRays;
 
 pub struct IntersectorPass {
     frame_bind_group_layout: wgpu::BindGroupLayout,
@@ -14,44 +17,115 @@ pub struct IntersectorPass {
 impl IntersectorPass {
     const RAY_BINDING: u32 = 0;
     const INTERSECTION_BINDING: u32 = 1;
+    const ACTIVE_RAY_BINDING: u32 = 2;
 
+    /// Create the pass.
+    ///
+    /// When `surface_layout` is provided, hits are alpha tested against
+    /// the material cutoff, and the surface bind group must be given
+    /// to [`IntersectorPass::dispatch`].
     pub fn new(
         device: &wgpu::Device,
         processor: &ShaderCache,
         geometry_layout: &crate::RTGeometryBindGroupLayout,
+        surface_layout: Option<&crate::RTSurfaceBindGroupLayout>,
+        source: Option<&str>,
+    ) -> Self {
+        Self::create(
+            device,
+            processor,
+            geometry_layout,
+            surface_layout,
+            source,
+            false,
+        )
+    }
+
+    /// Create a pass only intersecting the rays gathered by [`super::RayCompactionPass`].
+    ///
+    /// Bind groups are created with [`IntersectorPass::create_compacted_frame_bind_groups`],
+    /// and the pass is dispatched with [`IntersectorPass::dispatch_indirect`].
+    pub fn new_compacted(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        geometry_layout: &crate::RTGeometryBindGroupLayout,
+        surface_layout: Option<&crate::RTSurfaceBindGroupLayout>,
+        source: Option<&str>,
+    ) -> Self {
+        Self::create(
+            device,
+            processor,
+            geometry_layout,
+            surface_layout,
+            source,
+            true,
+        )
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        geometry_layout: &crate::RTGeometryBindGroupLayout,
+        surface_layout: Option<&crate::RTSurfaceBindGroupLayout>,
         source: Option<&str>,
+        compacted: bool,
     ) -> Self {
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: Self::RAY_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: Self::INTERSECTION_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let mut defines: FastHashMap<String, String> = FastHashMap::default();
+        if compacted {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: Self::ACTIVE_RAY_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+            defines.insert("COMPACTED_RAYS".into(), "".into());
+        }
         let frame_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Intersector Bind Group Layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: Self::RAY_BINDING,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: Self::INTERSECTION_BINDING,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
+                entries: &entries,
             });
 
+        let mut bind_group_layouts: Vec<&wgpu::BindGroupLayout> =
+            vec![geometry_layout, &frame_bind_group_layout];
+        if let Some(surface_layout) = surface_layout {
+            bind_group_layouts.push(surface_layout);
+            defines.insert("ALPHA_TEST".into(), "".into());
+        }
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Intersector Pipeline Layout"),
-            bind_group_layouts: &[geometry_layout, &frame_bind_group_layout],
-            push_constant_ranges: &[],
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
         });
 
         let module: wgpu::naga::Module = processor
@@ -65,7 +139,7 @@ impl IntersectorPass {
                     path_separator!(),
                     "intersection.comp"
                 ))),
-                None,
+                Some(&defines),
             )
             .unwrap();
 
@@ -95,29 +169,106 @@ impl IntersectorPass {
         out_intersections: gpu::StorageBufferSlice<uniforms::Intersection>,
         rays: gpu::StorageBufferSlice<uniforms::Ray>,
     ) -> wgpu::BindGroup {
+        self.create_bind_group(device, out_intersections, rays, None)
+    }
+
+    /// Create the bind group of a pass created with [`IntersectorPass::new_compacted`].
+    pub fn create_compacted_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        out_intersections: gpu::StorageBufferSlice<uniforms::Intersection>,
+        rays: gpu::StorageBufferSlice<uniforms::Ray>,
+        compacted: &CompactedRays,
+    ) -> wgpu::BindGroup {
+        self.create_bind_group(device, out_intersections, rays, Some(compacted))
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        out_intersections: gpu::StorageBufferSlice<uniforms::Intersection>,
+        rays: gpu::StorageBufferSlice<uniforms::Ray>,
+        compacted: Option<&CompactedRays>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: Self::RAY_BINDING,
+                resource: rays.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: Self::INTERSECTION_BINDING,
+                resource: out_intersections.as_entire_binding(),
+            },
+        ];
+        let active_rays = compacted.map(CompactedRays::active_rays);
+        if let Some(active_rays) = &active_rays {
+            entries.push(wgpu::BindGroupEntry {
+                binding: Self::ACTIVE_RAY_BINDING,
+                resource: active_rays.as_entire_binding(),
+            });
+        }
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Intersector Frame Bind Group"),
             layout: &self.frame_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: Self::RAY_BINDING,
-                    resource: rays.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: Self::INTERSECTION_BINDING,
-                    resource: out_intersections.as_entire_binding(),
-                },
-            ],
+            entries: &entries,
         })
     }
 
+    /// Intersect the rays with the scene.
+    ///
+    /// `ray_mask` is combined with the mask of each ray, instances
+    /// not overlapping the result are ignored.
+    ///
+    /// `surface_bind_group` is required if the pass was created with alpha testing.
     pub fn dispatch(
         &self,
         encoder: &mut wgpu::CommandEncoder,
         scene_bind_group: &wgpu::BindGroup,
         frame_bind_group: &wgpu::BindGroup,
+        surface_bind_group: Option<&wgpu::BindGroup>,
         dispatch_size: (u32, u32, u32),
+        ray_mask: VisibilityMask,
+    ) {
+        let mut pass = self.begin_pass(
+            encoder,
+            scene_bind_group,
+            frame_bind_group,
+            surface_bind_group,
+            ray_mask,
+        );
+        pass.dispatch_workgroups(dispatch_size.0, dispatch_size.1, dispatch_size.2);
+    }
+
+    /// Intersect the active rays of `compacted`.
+    ///
+    /// See [`IntersectorPass::dispatch`].
+    pub fn dispatch_indirect(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_bind_group: &wgpu::BindGroup,
+        frame_bind_group: &wgpu::BindGroup,
+        surface_bind_group: Option<&wgpu::BindGroup>,
+        compacted: &CompactedRays,
+        ray_mask: VisibilityMask,
     ) {
+        let mut pass = self.begin_pass(
+            encoder,
+            scene_bind_group,
+            frame_bind_group,
+            surface_bind_group,
+            ray_mask,
+        );
+        pass.dispatch_workgroups_indirect(compacted.dispatch_args(), 0);
+    }
+
+    fn begin_pass<'a>(
+        &self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        scene_bind_group: &wgpu::BindGroup,
+        frame_bind_group: &wgpu::BindGroup,
+        surface_bind_group: Option<&wgpu::BindGroup>,
+        ray_mask: VisibilityMask,
+    ) -> wgpu::ComputePass<'a> {
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Intersector Pass"),
             timestamp_writes: None,
@@ -125,6 +276,14 @@ impl IntersectorPass {
         pass.set_pipeline(&self.pipeline);
         pass.set_bind_group(0, scene_bind_group, &[]);
         pass.set_bind_group(1, frame_bind_group, &[]);
-        pass.dispatch_workgroups(dispatch_size.0, dispatch_size.1, dispatch_size.2);
+        if let Some(surface_bind_group) = surface_bind_group {
+            pass.set_bind_group(2, surface_bind_group, &[]);
+        }
+        {
+            let data = [ray_mask.bits() as u32];
+            let data = bytemuck::cast_slice(&data);
+            pass.set_push_constants(0, data);
+        }
+        pass
     }
 }
@@ -18,6 +18,8 @@ impl LightmapPass {
     const INDEX_BINDING: u32 = 2;
     const VERTEX_BINDING: u32 = 3;
     const PER_DRAW_STRUCT_BINDING: u32 = 4;
+    const TLAS_BINDING: u32 = 5;
+    const PROCEDURAL_BINDING: u32 = 6;
 
     pub fn new(
         device: &wgpu::Device,
@@ -77,6 +79,26 @@ impl LightmapPass {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::TLAS_BINDING,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::PROCEDURAL_BINDING,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -122,7 +144,8 @@ impl LightmapPass {
         let layout_builder = gpu::VertexBufferLayoutBuilder::new(2)
             .auto_attribute(wgpu::VertexFormat::Float32x4)
             .auto_attribute(wgpu::VertexFormat::Float32x4);
-        let layout = layout_builder.build(None);
+        // Tangents aren't used for baking.
+        let layout = layout_builder.build(Some(std::mem::size_of::<uniforms::Vertex>() as u64));
 
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Lightmap Pipeline"),
@@ -155,6 +178,7 @@ impl LightmapPass {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_frame_bind_groups(
         &self,
         device: &wgpu::Device,
@@ -163,6 +187,8 @@ impl LightmapPass {
         indices: &gpu::Buffer<u32>,
         vertices: &wgpu::Buffer,
         global_uniforms: gpu::UniformBufferSlice<uniforms::PerDrawUniforms>,
+        tlas: gpu::StorageBufferSlice<uniforms::TLASNode>,
+        procedurals: gpu::StorageBufferSlice<uniforms::ProceduralPrimitive>,
     ) -> BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Lightmap Bind Group"),
@@ -188,6 +214,14 @@ impl LightmapPass {
                     binding: Self::PER_DRAW_STRUCT_BINDING,
                     resource: global_uniforms.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: Self::TLAS_BINDING,
+                    resource: tlas.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::PROCEDURAL_BINDING,
+                    resource: procedurals.as_entire_binding(),
+                },
             ],
         })
     }
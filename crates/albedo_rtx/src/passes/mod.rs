@@ -1,24 +1,34 @@
 mod a_trous;
 mod accumulation;
+mod aov;
 mod blit_pass;
 mod blit_texture_pass;
+mod compaction;
 mod denoise;
 mod intersector;
 mod lightmap;
+mod path_tracer;
 mod ray;
 mod shading;
+mod shadow;
+mod taa;
 mod temporal_accumulation;
 
 pub use a_trous::ATrousPass;
 pub use accumulation::AccumulationPass;
+pub use aov::{AovFlags, AovTargets};
 pub use blit_pass::BlitPass;
 pub use blit_texture_pass::BlitTexturePass;
+pub use compaction::{CompactedRays, RayCompactionPass};
 pub use denoise::*;
 pub use intersector::IntersectorPass;
 pub use lightmap::LightmapPass;
+pub use path_tracer::{PathDepth, PathTracer, PathTracerBindGroups};
 pub use ray::RayPass;
 pub use shading::{PrimaryRayPass, ShadingPass};
-pub use temporal_accumulation::TemporalAccumulationPass;
+pub use shadow::ShadowPass;
+pub use taa::TaaPass;
+pub use temporal_accumulation::{DenoiseSignal, TemporalAccumulationPass};
 
 pub(crate) const GBUFFER_READ_TY: wgpu::BindingType = wgpu::BindingType::Texture {
     multisampled: false,
@@ -0,0 +1,264 @@
+use albedo_backend::data::{CompileError, PreprocessError, ShaderCache};
+use albedo_backend::gpu;
+use wgpu::naga::FastHashMap;
+
+use crate::uniforms::{PerDrawUniforms, VisibilityMask};
+use crate::{RTGeometryBindGroupLayout, RTSurfaceBindGroupLayout, RaytraceResources};
+
+use super::{
+    AovFlags, CompactedRays, IntersectorPass, RayCompactionPass, RayPass, ShadingPass, ShadowPass,
+};
+
+/// Number of bounces of the traced paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathDepth {
+    /// Bounces before paths can be terminated by russian roulette.
+    pub min_bounces: u32,
+    pub max_bounces: u32,
+}
+
+impl Default for PathDepth {
+    fn default() -> Self {
+        Self {
+            min_bounces: 3,
+            max_bounces: 8,
+        }
+    }
+}
+
+pub struct PathTracerBindGroups {
+    ray: wgpu::BindGroup,
+    compaction: wgpu::BindGroup,
+    intersection: wgpu::BindGroup,
+    shading: wgpu::BindGroup,
+    shadow: Option<wgpu::BindGroup>,
+}
+
+/// Path tracing driver.
+///
+/// Generates the camera rays, and runs the intersection and shading passes
+/// for each bounce. Paths are terminated with russian roulette after
+/// [`PathDepth::min_bounces`].
+///
+/// Terminated paths are removed from the wavefront before each bounce,
+/// and the following passes are dispatched indirectly over the remaining rays.
+/// The number of rays traced at each bounce is kept on the GPU,
+/// see [`PathTracer::live_rays`].
+///
+/// When created with `NEXT_EVENT_ESTIMATION`, shadow rays are traced after
+/// each shading pass.
+///
+/// Outputs such as albedo or per-bounce radiance are written by the shading
+/// pass when created with [`super::AovFlags::insert_defines`], and the
+/// per-bounce radiance completed by the shadow pass.
+///
+/// The G-buffer used by the denoiser isn't supported: `EMIT_GBUFFER` is rejected,
+/// and the primary hits must be shaded with a [`super::PrimaryRayPass`] instead.
+pub struct PathTracer {
+    ray_pass: RayPass,
+    compaction: RayCompactionPass,
+    intersector: IntersectorPass,
+    shading: ShadingPass,
+    shadow: Option<ShadowPass>,
+    live_rays: gpu::Buffer<u32>,
+    compacted: CompactedRays,
+    depth: PathDepth,
+}
+
+impl PathTracer {
+    /// Create the driver.
+    ///
+    /// `defines` are forwarded to the shading pass, and `ray_count` is the
+    /// size of the ray buffer, see [`PathTracer::resize`].
+    ///
+    /// Returns [`PreprocessError::UnsupportedDefine`] for `EMIT_GBUFFER`.
+    pub fn new(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        geometry_layout: &RTGeometryBindGroupLayout,
+        surface_layout: &RTSurfaceBindGroupLayout,
+        defines: &FastHashMap<String, String>,
+        depth: PathDepth,
+        ray_count: u64,
+    ) -> Result<Self, CompileError> {
+        // The G-buffer requires push constants, and must only be written by the primary hits.
+        if defines.contains_key("EMIT_GBUFFER") {
+            return Err(PreprocessError::UnsupportedDefine("EMIT_GBUFFER".into()).into());
+        }
+
+        let mut defines = defines.clone();
+        defines.insert("RUSSIAN_ROULETTE".into(), "".into());
+        defines.insert("COMPACTED_RAYS".into(), "".into());
+
+        let shadow = if defines.contains_key("NEXT_EVENT_ESTIMATION") {
+            Some(ShadowPass::new_compacted(
+                device,
+                processor,
+                geometry_layout,
+                Some(surface_layout),
+                AovFlags::from_defines(&defines),
+                None,
+            ))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            ray_pass: RayPass::new(device, processor, None),
+            compaction: RayCompactionPass::new(device, None),
+            intersector: IntersectorPass::new_compacted(
+                device,
+                processor,
+                geometry_layout,
+                Some(surface_layout),
+                None,
+            ),
+            shading: ShadingPass::new(
+                device,
+                processor,
+                &defines,
+                geometry_layout,
+                surface_layout,
+            )?,
+            shadow,
+            live_rays: gpu::Buffer::new_storage(
+                device,
+                depth.max_bounces.max(1) as u64,
+                Some(gpu::BufferInitDescriptor::new(
+                    Some("Live Rays Buffer"),
+                    wgpu::BufferUsages::COPY_SRC,
+                )),
+            ),
+            compacted: CompactedRays::new(device, ray_count),
+            depth,
+        })
+    }
+
+    /// Grow the compacted ray list to hold `ray_count` rays.
+    ///
+    /// The list is only reallocated when too small, in which case the
+    /// bind groups must be created again.
+    pub fn resize(&mut self, device: &wgpu::Device, ray_count: u64) {
+        if ray_count > self.compacted.capacity() {
+            self.compacted = CompactedRays::new(device, ray_count);
+        }
+    }
+
+    pub fn depth(&self) -> PathDepth {
+        self.depth
+    }
+
+    /// Number of rays traced at each bounce, i.e., paths still alive after the previous one.
+    ///
+    /// Reset at the start of each [`PathTracer::dispatch`], and can be copied
+    /// to a mappable buffer to be read back.
+    pub fn live_rays(&self) -> &gpu::Buffer<u32> {
+        &self.live_rays
+    }
+
+    /// Write the path depth in the per-draw uniforms.
+    ///
+    /// Must be uploaded before calling [`PathTracer::dispatch`].
+    pub fn update_uniforms(&self, uniforms: &mut PerDrawUniforms) {
+        uniforms.bounces = self.depth.max_bounces;
+        uniforms.min_bounces = self.depth.min_bounces;
+    }
+
+    /// Create the bind groups of all passes.
+    ///
+    /// `resources.shadow_rays` is required when created with next event estimation,
+    /// and `resources.aovs` when created with the defines of [`super::AovFlags`].
+    pub fn create_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        resources: &RaytraceResources,
+    ) -> PathTracerBindGroups {
+        assert!(
+            resources.rays.count() <= self.compacted.capacity(),
+            "rays don't fit in the compacted list, see `PathTracer::resize`"
+        );
+        let compacted = &self.compacted;
+        let shadow = self.shadow.as_ref().map(|shadow| {
+            let Some(shadow_rays) = resources.shadow_rays else {
+                panic!("next event estimation requires shadow rays")
+            };
+            shadow.create_compacted_frame_bind_groups(
+                device,
+                resources.rays,
+                shadow_rays,
+                resources.aovs,
+                compacted,
+            )
+        });
+        let shading_resources = RaytraceResources {
+            active_rays: Some(compacted.active_rays()),
+            ..*resources
+        };
+        PathTracerBindGroups {
+            ray: self.ray_pass.create_frame_bind_groups(
+                device,
+                resources.rays,
+                resources.camera_uniforms,
+                resources.global_uniforms,
+            ),
+            compaction: self
+                .compaction
+                .create_frame_bind_groups(device, resources.rays, compacted),
+            intersection: self.intersector.create_compacted_frame_bind_groups(
+                device,
+                resources.intersections,
+                resources.rays,
+                compacted,
+            ),
+            shading: self
+                .shading
+                .bgl
+                .as_bind_group(device, &shading_resources, None),
+            shadow,
+        }
+    }
+
+    /// Trace one sample per pixel, for paths of up to [`PathDepth::max_bounces`] bounces.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        geometry_bind_group: &wgpu::BindGroup,
+        surface_bind_group: &wgpu::BindGroup,
+        bind_groups: &PathTracerBindGroups,
+        size: (u32, u32, u32),
+    ) {
+        encoder.clear_buffer(self.live_rays.inner(), 0, None);
+
+        self.ray_pass.dispatch(encoder, &bind_groups.ray, size);
+        let compacted = &self.compacted;
+        for bounce in 0..self.depth.max_bounces {
+            self.compaction
+                .dispatch(encoder, &bind_groups.compaction, compacted, size);
+            compacted.copy_count(encoder, self.live_rays.inner(), bounce as u64);
+            self.intersector.dispatch_indirect(
+                encoder,
+                geometry_bind_group,
+                &bind_groups.intersection,
+                Some(surface_bind_group),
+                compacted,
+                VisibilityMask::all(),
+            );
+            self.shading.dispatch_indirect(
+                encoder,
+                geometry_bind_group,
+                surface_bind_group,
+                &bind_groups.shading,
+                compacted,
+            );
+            if let (Some(shadow), Some(bind_group)) = (&self.shadow, &bind_groups.shadow) {
+                shadow.dispatch_indirect(
+                    encoder,
+                    geometry_bind_group,
+                    bind_group,
+                    Some(surface_bind_group),
+                    compacted,
+                );
+            }
+        }
+    }
+}
@@ -14,17 +14,22 @@ use bitflags::bitflags;
 use wgpu::naga::FastHashMap;
 use wgpu::PushConstantRange;
 
+use super::AovFlags;
+use super::CompactedRays;
 use super::GBUFFER_WRITE_TY;
 
 bitflags! {
     pub struct ShadingFlags: u32 {
         const EMIT_GBUFFER = 0b00000001;
+        const NEXT_EVENT_ESTIMATION = 0b00000010;
+        const COMPACTED_RAYS = 0b00000100;
     }
 }
 
 pub struct ShadingBindGroupLayout {
     inner: wgpu::BindGroupLayout,
     flags: ShadingFlags,
+    aovs: AovFlags,
 }
 
 impl ShadingBindGroupLayout {
@@ -33,6 +38,9 @@ impl ShadingBindGroupLayout {
     const PER_DRAW_STRUCT_BINDING: u32 = 2;
     const GBUFFER_BINDING: u32 = 3;
     const MOTION_BINDING: u32 = 4;
+    const SHADOW_RAY_BINDING: u32 = 5;
+    const ACTIVE_RAY_BINDING: u32 = 6;
+    const REFLECTANCE_BINDING: u32 = 7;
 
     pub fn new(device: &wgpu::Device, defines: &FastHashMap<String, String>) -> Self {
         let flags = {
@@ -40,8 +48,15 @@ impl ShadingBindGroupLayout {
             if defines.contains_key("EMIT_GBUFFER") {
                 f = f | ShadingFlags::EMIT_GBUFFER;
             }
+            if defines.contains_key("NEXT_EVENT_ESTIMATION") {
+                f = f | ShadingFlags::NEXT_EVENT_ESTIMATION;
+            }
+            if defines.contains_key("COMPACTED_RAYS") {
+                f = f | ShadingFlags::COMPACTED_RAYS;
+            }
             f
         };
+        let aovs = AovFlags::from_defines(defines);
 
         let mut entries: Vec<wgpu::BindGroupLayoutEntry> = Vec::new();
         entries.extend_from_slice(&[
@@ -95,8 +110,43 @@ impl ShadingBindGroupLayout {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::REFLECTANCE_BINDING,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        format: wgpu::TextureFormat::Rg32Uint,
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
             ]);
         }
+        if flags.contains(ShadingFlags::NEXT_EVENT_ESTIMATION) {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: Self::SHADOW_RAY_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+        }
+        if flags.contains(ShadingFlags::COMPACTED_RAYS) {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: Self::ACTIVE_RAY_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+        }
+        entries.extend(aovs.layout_entries());
 
         Self {
             inner: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -104,6 +154,7 @@ impl ShadingBindGroupLayout {
                 entries: &entries,
             }),
             flags,
+            aovs,
         }
     }
 
@@ -142,6 +193,34 @@ impl ShadingBindGroupLayout {
                 binding: Self::MOTION_BINDING,
                 resource: wgpu::BindingResource::TextureView(denoise.motion),
             });
+            entries.push(wgpu::BindGroupEntry {
+                binding: Self::REFLECTANCE_BINDING,
+                resource: wgpu::BindingResource::TextureView(denoise.reflectance),
+            });
+        }
+        if self.flags.contains(ShadingFlags::NEXT_EVENT_ESTIMATION) {
+            let Some(shadow_rays) = &resources.shadow_rays else {
+                panic!("next event estimation requires shadow rays")
+            };
+            entries.push(wgpu::BindGroupEntry {
+                binding: Self::SHADOW_RAY_BINDING,
+                resource: shadow_rays.as_entire_binding(),
+            });
+        }
+        if self.flags.contains(ShadingFlags::COMPACTED_RAYS) {
+            let Some(active_rays) = &resources.active_rays else {
+                panic!("compacted shading requires active rays")
+            };
+            entries.push(wgpu::BindGroupEntry {
+                binding: Self::ACTIVE_RAY_BINDING,
+                resource: active_rays.as_entire_binding(),
+            });
+        }
+        if !self.aovs.is_empty() {
+            let Some(aovs) = resources.aovs else {
+                panic!("shading with AOVs requires AOV targets")
+            };
+            entries.extend(aovs.entries(self.aovs));
         }
 
         device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -227,6 +306,28 @@ impl ShadingPass {
         pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
     }
 
+    /// Shade the active rays of `compacted`.
+    ///
+    /// The pass must be created with `COMPACTED_RAYS`.
+    pub fn dispatch_indirect(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        geometry_bindgroup: &wgpu::BindGroup,
+        surface_bindgroup: &wgpu::BindGroup,
+        frame_bind_groups: &wgpu::BindGroup,
+        compacted: &CompactedRays,
+    ) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Shading Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, geometry_bindgroup, &[]);
+        pass.set_bind_group(1, surface_bindgroup, &[]);
+        pass.set_bind_group(2, frame_bind_groups, &[]);
+        pass.dispatch_workgroups_indirect(compacted.dispatch_args(), 0);
+    }
+
     pub fn new_raw(
         device: &wgpu::Device,
         geometry_layout: &RTGeometryBindGroupLayout,
@@ -275,20 +376,23 @@ impl ShadingPass {
 pub struct PrimaryRayPass(ShadingPass);
 
 impl PrimaryRayPass {
-    fn defines() -> FastHashMap<String, String> {
+    fn defines(aovs: AovFlags) -> FastHashMap<String, String> {
         // @todo: Replace by shader flags
         let mut defines: FastHashMap<String, String> = FastHashMap::default();
         defines.insert("EMIT_GBUFFER".into(), "".into());
+        aovs.insert_defines(&mut defines);
         defines
     }
 
+    /// Create the pass, writing the outputs of `aovs` in addition to the gbuffer.
     pub fn new(
         device: &wgpu::Device,
         processor: &ShaderCache,
         geometry_layout: &RTGeometryBindGroupLayout,
         surface_layout: &RTSurfaceBindGroupLayout,
+        aovs: AovFlags,
     ) -> Result<Self, CompileError> {
-        let defines = PrimaryRayPass::defines();
+        let defines = PrimaryRayPass::defines(aovs);
         Ok(Self {
             0: ShadingPass::new(device, processor, &defines, geometry_layout, surface_layout)?,
         })
@@ -299,8 +403,9 @@ impl PrimaryRayPass {
         processor: &ShaderCache,
         geometry_layout: &RTGeometryBindGroupLayout,
         surface_layout: &RTSurfaceBindGroupLayout,
+        aovs: AovFlags,
     ) -> Self {
-        let defines = PrimaryRayPass::defines();
+        let defines = PrimaryRayPass::defines(aovs);
         Self {
             0: ShadingPass::new_inlined(
                 device,
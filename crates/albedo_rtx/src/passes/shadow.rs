@@ -0,0 +1,311 @@
+use std::borrow::Cow;
+
+use albedo_backend::{data::ShaderCache, gpu};
+use wgpu::naga::FastHashMap;
+use wgpu::ShaderModuleDescriptor;
+
+use crate::get_dispatch_size;
+use crate::macros::path_separator;
+use crate::uniforms;
+
+use super::{AovFlags, AovTargets, CompactedRays};
+
+/// Traces the shadow rays written by the shading pass.
+///
+/// Used for next event estimation: the light contribution of each shadow ray
+/// is added to its path if nothing occludes the light. Must be dispatched
+/// after the shading pass, with the same size.
+///
+/// With [`AovFlags::BOUNCE_RADIANCE`], the light samples are visible only after
+/// this pass: it writes the per-bounce radiance in place of the shading pass.
+pub struct ShadowPass {
+    frame_bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    aovs: AovFlags,
+}
+
+impl ShadowPass {
+    const RAY_BINDING: u32 = 0;
+    const SHADOW_RAY_BINDING: u32 = 1;
+    const ACTIVE_RAY_BINDING: u32 = 2;
+
+    const WORKGROUP_SIZE: (u32, u32, u32) = (8, 8, 1);
+
+    /// Create the pass.
+    ///
+    /// When `surface_layout` is provided, shadows are alpha tested against
+    /// the material cutoff, and the surface bind group must be given
+    /// to [`ShadowPass::dispatch`].
+    ///
+    /// `aovs` must match the outputs of the shading pass. Only
+    /// [`AovFlags::BOUNCE_RADIANCE`] is written by this pass.
+    pub fn new(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        geometry_layout: &crate::RTGeometryBindGroupLayout,
+        surface_layout: Option<&crate::RTSurfaceBindGroupLayout>,
+        aovs: AovFlags,
+        source: Option<&str>,
+    ) -> Self {
+        Self::create(
+            device,
+            processor,
+            geometry_layout,
+            surface_layout,
+            aovs,
+            source,
+            false,
+        )
+    }
+
+    /// Create a pass only tracing the shadow rays of the paths gathered
+    /// by [`super::RayCompactionPass`].
+    ///
+    /// Bind groups are created with [`ShadowPass::create_compacted_frame_bind_groups`],
+    /// and the pass is dispatched with [`ShadowPass::dispatch_indirect`].
+    pub fn new_compacted(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        geometry_layout: &crate::RTGeometryBindGroupLayout,
+        surface_layout: Option<&crate::RTSurfaceBindGroupLayout>,
+        aovs: AovFlags,
+        source: Option<&str>,
+    ) -> Self {
+        Self::create(
+            device,
+            processor,
+            geometry_layout,
+            surface_layout,
+            aovs,
+            source,
+            true,
+        )
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        geometry_layout: &crate::RTGeometryBindGroupLayout,
+        surface_layout: Option<&crate::RTSurfaceBindGroupLayout>,
+        aovs: AovFlags,
+        source: Option<&str>,
+        compacted: bool,
+    ) -> Self {
+        let aovs = aovs & AovFlags::BOUNCE_RADIANCE;
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: Self::RAY_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: Self::SHADOW_RAY_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let mut defines: FastHashMap<String, String> = FastHashMap::default();
+        if compacted {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: Self::ACTIVE_RAY_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+            defines.insert("COMPACTED_RAYS".into(), "".into());
+        }
+        entries.extend(aovs.layout_entries());
+        aovs.insert_defines(&mut defines);
+        let frame_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Shadow Bind Group Layout"),
+                entries: &entries,
+            });
+
+        let mut bind_group_layouts: Vec<&wgpu::BindGroupLayout> =
+            vec![geometry_layout, &frame_bind_group_layout];
+        if let Some(surface_layout) = surface_layout {
+            bind_group_layouts.push(surface_layout);
+            defines.insert("ALPHA_TEST".into(), "".into());
+        }
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let module: wgpu::naga::Module = processor
+            .compile_compute(
+                source.unwrap_or(include_str!(concat!(
+                    "..",
+                    path_separator!(),
+                    "..",
+                    path_separator!(),
+                    "shaders",
+                    path_separator!(),
+                    "shadow.comp"
+                ))),
+                Some(&defines),
+            )
+            .unwrap();
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Naga(Cow::Owned(module)),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            entry_point: Some("main"),
+            module: &shader,
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        ShadowPass {
+            frame_bind_group_layout,
+            pipeline,
+            aovs,
+        }
+    }
+
+    /// Create the bind group of the pass.
+    ///
+    /// `aovs` is required when created with [`AovFlags::BOUNCE_RADIANCE`].
+    pub fn create_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        rays: gpu::StorageBufferSlice<uniforms::Ray>,
+        shadow_rays: gpu::StorageBufferSlice<uniforms::ShadowRay>,
+        aovs: Option<&AovTargets>,
+    ) -> wgpu::BindGroup {
+        self.create_bind_group(device, rays, shadow_rays, aovs, None)
+    }
+
+    /// Create the bind group of a pass created with [`ShadowPass::new_compacted`].
+    pub fn create_compacted_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        rays: gpu::StorageBufferSlice<uniforms::Ray>,
+        shadow_rays: gpu::StorageBufferSlice<uniforms::ShadowRay>,
+        aovs: Option<&AovTargets>,
+        compacted: &CompactedRays,
+    ) -> wgpu::BindGroup {
+        self.create_bind_group(device, rays, shadow_rays, aovs, Some(compacted))
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        rays: gpu::StorageBufferSlice<uniforms::Ray>,
+        shadow_rays: gpu::StorageBufferSlice<uniforms::ShadowRay>,
+        aovs: Option<&AovTargets>,
+        compacted: Option<&CompactedRays>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: Self::RAY_BINDING,
+                resource: rays.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: Self::SHADOW_RAY_BINDING,
+                resource: shadow_rays.as_entire_binding(),
+            },
+        ];
+        let active_rays = compacted.map(CompactedRays::active_rays);
+        if let Some(active_rays) = &active_rays {
+            entries.push(wgpu::BindGroupEntry {
+                binding: Self::ACTIVE_RAY_BINDING,
+                resource: active_rays.as_entire_binding(),
+            });
+        }
+        if !self.aovs.is_empty() {
+            let Some(aovs) = aovs else {
+                panic!("missing AOV targets: {:?}", self.aovs)
+            };
+            entries.extend(aovs.entries(self.aovs));
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Frame Bind Group"),
+            layout: &self.frame_bind_group_layout,
+            entries: &entries,
+        })
+    }
+
+    /// Trace the shadow rays, and add the contribution of visible lights.
+    ///
+    /// `surface_bind_group` is required if the pass was created with alpha testing.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_bind_group: &wgpu::BindGroup,
+        frame_bind_group: &wgpu::BindGroup,
+        surface_bind_group: Option<&wgpu::BindGroup>,
+        size: (u32, u32, u32),
+    ) {
+        let workgroups = get_dispatch_size(&size, &Self::WORKGROUP_SIZE);
+        let mut pass = self.begin_pass(
+            encoder,
+            scene_bind_group,
+            frame_bind_group,
+            surface_bind_group,
+        );
+        pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+    }
+
+    /// Trace the shadow rays of the active paths of `compacted`.
+    ///
+    /// See [`ShadowPass::dispatch`].
+    pub fn dispatch_indirect(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        scene_bind_group: &wgpu::BindGroup,
+        frame_bind_group: &wgpu::BindGroup,
+        surface_bind_group: Option<&wgpu::BindGroup>,
+        compacted: &CompactedRays,
+    ) {
+        let mut pass = self.begin_pass(
+            encoder,
+            scene_bind_group,
+            frame_bind_group,
+            surface_bind_group,
+        );
+        pass.dispatch_workgroups_indirect(compacted.dispatch_args(), 0);
+    }
+
+    fn begin_pass<'a>(
+        &self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        scene_bind_group: &wgpu::BindGroup,
+        frame_bind_group: &wgpu::BindGroup,
+        surface_bind_group: Option<&wgpu::BindGroup>,
+    ) -> wgpu::ComputePass<'a> {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Shadow Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, scene_bind_group, &[]);
+        pass.set_bind_group(1, frame_bind_group, &[]);
+        if let Some(surface_bind_group) = surface_bind_group {
+            pass.set_bind_group(2, surface_bind_group, &[]);
+        }
+        pass
+    }
+}
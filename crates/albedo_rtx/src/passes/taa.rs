@@ -0,0 +1,227 @@
+use std::borrow::Cow;
+
+use albedo_backend::data::{CompileError, PreprocessError, ShaderCache};
+
+use crate::get_dispatch_size;
+use crate::macros::path_separator;
+
+/// Temporal anti-aliasing resolve, with neighborhood clamping.
+///
+/// Blends the current frame with the reprojected output of the previous frame.
+/// The history is clamped to the color bounds of the 3x3 neighborhood of
+/// the pixel, to reject disoccluded and stale samples.
+///
+/// Frames must be rendered with sub-pixel offsets, see [`crate::Jitter`].
+/// The pass runs after [`super::CompositingPass`] using the motion of the
+/// primary hits, or on its own for static images, e.g., rasterized previews.
+///
+/// The output becomes the history of the next frame: bind groups are usually
+/// created for two outputs, swapped every frame.
+pub struct TaaPass {
+    frame_bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    no_motion: wgpu::TextureView,
+}
+
+impl TaaPass {
+    const WORKGROUP_SIZE: (u32, u32, u32) = (8, 8, 1);
+    const SHADER_ID: &'static str = "taa.comp";
+
+    const COLOR_BINDING: u32 = 0;
+    const HISTORY_BINDING: u32 = 1;
+    const MOTION_BINDING: u32 = 2;
+    const COLOR_OUT_BINDING: u32 = 3;
+    const SAMPLER_BINDING: u32 = 4;
+
+    /// Weight of the history for a converged, static image.
+    pub const DEFAULT_HISTORY_WEIGHT: f32 = 0.9;
+
+    pub fn new_inlined(device: &wgpu::Device, processor: &ShaderCache) -> Self {
+        Self::new_raw(
+            device,
+            processor,
+            include_str!(concat!(
+                "..",
+                path_separator!(),
+                "..",
+                path_separator!(),
+                "shaders",
+                path_separator!(),
+                "taa.comp"
+            )),
+        )
+        .unwrap()
+    }
+
+    pub fn new(device: &wgpu::Device, processor: &ShaderCache) -> Result<Self, CompileError> {
+        let Some(source) = processor.get(Self::SHADER_ID) else {
+            return Err(PreprocessError::Missing(Self::SHADER_ID.to_string()).into());
+        };
+        Self::new_raw(device, processor, source)
+    }
+
+    fn new_raw(
+        device: &wgpu::Device,
+        processor: &ShaderCache,
+        src: &str,
+    ) -> Result<Self, CompileError> {
+        let texture_ty = wgpu::BindingType::Texture {
+            multisampled: false,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+        };
+        let frame_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("TAA Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::COLOR_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: texture_ty,
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::HISTORY_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: texture_ty,
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::MOTION_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: texture_ty,
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::COLOR_OUT_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            format: wgpu::TextureFormat::Rgba32Float,
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::SAMPLER_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let pipeline_layout: wgpu::PipelineLayout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("TAA Pipeline Layout"),
+                bind_group_layouts: &[&frame_bind_group_layout],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::COMPUTE,
+                    range: 0..4,
+                }],
+            });
+
+        let module = processor.compile_compute(src, None)?;
+        let shader: wgpu::ShaderModule =
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("TAA Shader"),
+                source: wgpu::ShaderSource::Naga(Cow::Owned(module)),
+            });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("TAA Pipeline"),
+            layout: Some(&pipeline_layout),
+            entry_point: Some("main"),
+            module: &shader,
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        // Zero initialized, used when no motion is provided.
+        let no_motion = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("TAA Empty Motion"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rg32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self {
+            frame_bind_group_layout,
+            pipeline,
+            no_motion,
+        })
+    }
+
+    /// Create the bind group resolving `color` into `out_color`.
+    ///
+    /// `history` is the output of the previous frame. Without `motion`,
+    /// the image is assumed to be static.
+    pub fn create_frame_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        out_color: &wgpu::TextureView,
+        color: &wgpu::TextureView,
+        history: &wgpu::TextureView,
+        motion: Option<&wgpu::TextureView>,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TAA Frame Bind Group"),
+            layout: &self.frame_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: Self::COLOR_BINDING,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::HISTORY_BINDING,
+                    resource: wgpu::BindingResource::TextureView(history),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::MOTION_BINDING,
+                    resource: wgpu::BindingResource::TextureView(motion.unwrap_or(&self.no_motion)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::COLOR_OUT_BINDING,
+                    resource: wgpu::BindingResource::TextureView(out_color),
+                },
+                wgpu::BindGroupEntry {
+                    binding: Self::SAMPLER_BINDING,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Resolve the current frame.
+    ///
+    /// `history_weight` is usually [`TaaPass::DEFAULT_HISTORY_WEIGHT`],
+    /// and `0` to discard the history, e.g., for the first frame or after a camera cut.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_bind_group: &wgpu::BindGroup,
+        size: &(u32, u32, u32),
+        history_weight: f32,
+    ) {
+        let workgroups = get_dispatch_size(size, &Self::WORKGROUP_SIZE);
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("TAA Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, frame_bind_group, &[]);
+        pass.set_push_constants(0, bytemuck::bytes_of(&history_weight));
+        pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+    }
+}
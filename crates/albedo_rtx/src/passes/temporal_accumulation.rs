@@ -8,6 +8,17 @@ use crate::{get_dispatch_size, uniforms};
 
 use super::GBUFFER_READ_TY;
 
+/// Signal accumulated by [`TemporalAccumulationPass`].
+///
+/// Paths contribute to both signals, weighted by the diffuse and specular
+/// lobes of the BSDF at their primary hit.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DenoiseSignal {
+    Diffuse = 0,
+    Specular = 1,
+}
+
 pub struct TemporalAccumulationPass {
     frame_bind_group_layout: wgpu::BindGroupLayout,
     pipeline: wgpu::ComputePipeline,
@@ -28,6 +39,7 @@ impl TemporalAccumulationPass {
     const HISTORY_BINDING: u32 = 8;
     const MOMENTS_PREVIOUS_BINDING: u32 = 9;
     const MOMENTS_BINDING: u32 = 10;
+    const PARAMETERS_BINDING: u32 = 11;
 
     pub fn new_inlined(device: &wgpu::Device, processor: &ShaderCache) -> Self {
         Self::new_raw(
@@ -160,13 +172,26 @@ impl TemporalAccumulationPass {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: Self::PARAMETERS_BINDING,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Temporal Accumulation Pipeline Layout"),
             bind_group_layouts: &[&frame_bind_group_layout],
-            push_constant_ranges: &[],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
         });
 
         let module = processor.compile_compute(source, None)?;
@@ -205,6 +230,7 @@ impl TemporalAccumulationPass {
         sampler: &wgpu::Sampler,
         history_previous: &gpu::Buffer<u32>,
         moments_previous: &wgpu::TextureView,
+        parameters: gpu::UniformBufferSlice<uniforms::SvgfParameters>,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Temporal Accumulation Frame Bind Group"),
@@ -254,6 +280,10 @@ impl TemporalAccumulationPass {
                     binding: Self::MOMENTS_BINDING,
                     resource: wgpu::BindingResource::TextureView(out_moments),
                 },
+                wgpu::BindGroupEntry {
+                    binding: Self::PARAMETERS_BINDING,
+                    resource: parameters.as_entire_binding(),
+                },
             ],
         })
     }
@@ -263,6 +293,7 @@ impl TemporalAccumulationPass {
         encoder: &mut wgpu::CommandEncoder,
         frame_bind_group: &wgpu::BindGroup,
         size: &(u32, u32, u32),
+        signal: DenoiseSignal,
     ) {
         let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Temporal Accumulation Pass"),
@@ -271,6 +302,7 @@ impl TemporalAccumulationPass {
         let workgroups = get_dispatch_size(&size, &Self::WORKGROUP_SIZE);
         pass.set_pipeline(&self.pipeline);
         pass.set_bind_group(0, frame_bind_group, &[]);
+        pass.set_push_constants(0, bytemuck::bytes_of(&(signal as u32)));
         pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
     }
 }
@@ -0,0 +1,150 @@
+use glam::{Vec2, Vec3};
+
+/// Sine of the angle between texture coordinates edges, under which a
+/// triangle is considered degenerate.
+const DEGENERATE_UV_SINE: f32 = 1e-6;
+
+/// Generate per-vertex tangents, for normal mapping.
+///
+/// Tangents of each triangle are derived from the texture coordinates, and
+/// accumulated on its vertices before being orthogonalized against the normal,
+/// similar to MikkTSpace. The bitangent handedness is stored in `w`.
+///
+/// `indices` is `None` for non-indexed meshes. Vertices without texture
+/// coordinates variation get a zero tangent, i.e., no normal mapping.
+pub fn generate_tangents(
+    positions: pas::Slice<[f32; 4]>,
+    normals: pas::Slice<[f32; 3]>,
+    texcoords: pas::Slice<[f32; 2]>,
+    indices: Option<&[u32]>,
+) -> Vec<[f32; 4]> {
+    let count = positions.len();
+    let mut tangents = vec![Vec3::ZERO; count];
+    let mut bitangents = vec![Vec3::ZERO; count];
+
+    let index = |i: usize| indices.map_or(i, |indices| indices[i] as usize);
+    let triangle_count = indices.map_or(count, |indices| indices.len()) / 3;
+    for triangle in 0..triangle_count {
+        let ids = [
+            index(triangle * 3),
+            index(triangle * 3 + 1),
+            index(triangle * 3 + 2),
+        ];
+        let p = ids.map(|i| Vec3::from_slice(&positions[i][0..3]));
+        let uv = ids.map(|i| Vec2::from(texcoords[i]));
+
+        let e1 = p[1] - p[0];
+        let e2 = p[2] - p[0];
+        let d1 = uv[1] - uv[0];
+        let d2 = uv[2] - uv[0];
+        let det = d1.x * d2.y - d2.x * d1.y;
+        // Relative to the edges, so that small texture coordinates are supported.
+        if det.abs() <= DEGENERATE_UV_SINE * d1.length() * d2.length() {
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = (e1 * d2.y - e2 * d1.y) * r;
+        let bitangent = (e2 * d1.x - e1 * d2.x) * r;
+        for i in ids {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    (0..count)
+        .map(|i| {
+            let n = Vec3::from(normals[i]);
+            let t = (tangents[i] - n * n.dot(tangents[i])).normalize_or_zero();
+            let w = if n.cross(t).dot(bitangents[i]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [t.x, t.y, t.z, w]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit quad in the `xy` plane, facing `+z`.
+    const QUAD: [[f32; 4]; 4] = [
+        [0.0, 0.0, 0.0, 1.0],
+        [1.0, 0.0, 0.0, 1.0],
+        [1.0, 1.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0, 1.0],
+    ];
+    const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    /// Tangents of the quad, with texture coordinates computed from the positions.
+    fn quad_tangents(texcoord: impl Fn([f32; 4]) -> [f32; 2], indexed: bool) -> Vec<[f32; 4]> {
+        let (positions, indices): (Vec<[f32; 4]>, _) = if indexed {
+            (QUAD.to_vec(), Some(&QUAD_INDICES[..]))
+        } else {
+            (
+                QUAD_INDICES.iter().map(|&i| QUAD[i as usize]).collect(),
+                None,
+            )
+        };
+        let normals: Vec<[f32; 3]> = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let texcoords: Vec<[f32; 2]> = positions.iter().copied().map(texcoord).collect();
+        generate_tangents(
+            pas::Slice::new(&positions, 0),
+            pas::Slice::new(&normals, 0),
+            pas::Slice::new(&texcoords, 0),
+            indices,
+        )
+    }
+
+    fn assert_tangents(tangents: &[[f32; 4]], expected: [f32; 4]) {
+        for tangent in tangents {
+            let error = (glam::Vec4::from(*tangent) - glam::Vec4::from(expected))
+                .abs()
+                .max_element();
+            assert!(error < 1e-5, "expected {:?}, got {:?}", expected, tangent);
+        }
+    }
+
+    #[test]
+    fn quad_known_tangents() {
+        for indexed in [true, false] {
+            let tangents = quad_tangents(|p| [p[0], p[1]], indexed);
+            assert_tangents(&tangents, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn mirrored_texcoords() {
+        for indexed in [true, false] {
+            // Mirroring `u` flips the tangent, and the handedness.
+            let tangents = quad_tangents(|p| [1.0 - p[0], p[1]], indexed);
+            assert_tangents(&tangents, [-1.0, 0.0, 0.0, -1.0]);
+
+            // Mirroring `v` only flips the handedness.
+            let tangents = quad_tangents(|p| [p[0], 1.0 - p[1]], indexed);
+            assert_tangents(&tangents, [1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn rotated_texcoords() {
+        // `u` follows `+y`: the tangent follows it, and `v` follows `-x`.
+        let tangents = quad_tangents(|p| [p[1], 1.0 - p[0]], true);
+        assert_tangents(&tangents, [0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn small_texcoords() {
+        // Triangles covering a few texels of a large atlas.
+        let tangents = quad_tangents(|p| [0.5 + p[0] * 1e-4, 0.5 + p[1] * 1e-4], true);
+        assert_tangents(&tangents, [1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn degenerate_texcoords() {
+        let tangents = quad_tangents(|p| [p[0] + p[1], p[0] + p[1]], true);
+        assert_tangents(&tangents, [0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,108 @@
+use obvhs::{
+    aabb::Aabb,
+    ploc::{PlocSearchDistance, SortPrecision},
+};
+
+use crate::{BLASArray, TLASNode};
+
+/// Top-level acceleration structure over [`BLASArray::instances`].
+///
+/// The hierarchy is a binary BVH built over the world space bounds
+/// of each instance. It's traversed on the GPU before descending into
+/// the per-instance CWBVH.
+///
+/// The TLAS must be rebuilt whenever instances are added, removed, or moved.
+///
+/// The traversal stack holds [`TLAS::STACK_SIZE`] nodes: building a deeper
+/// hierarchy panics.
+#[derive(Default)]
+pub struct TLAS {
+    pub nodes: Vec<TLASNode>,
+}
+
+impl TLAS {
+    /// Maximum depth of the hierarchy.
+    ///
+    /// Must match `TLAS_STACK_SIZE` in `intersection_utils.glsl`.
+    pub const STACK_SIZE: usize = 64;
+
+    /// Search depth below which the builder falls back to a search distance of 1.
+    const SEARCH_DEPTH_THRESHOLD: usize = 3;
+
+    pub fn new(blas: &BLASArray) -> Self {
+        let mut tlas = Self::default();
+        tlas.build(blas);
+        tlas
+    }
+
+    pub fn build(&mut self, blas: &BLASArray) {
+        self.nodes.clear();
+
+        let mut aabbs: Vec<Aabb> = Vec::with_capacity(blas.instances.len());
+        let mut indices: Vec<u32> = Vec::with_capacity(blas.instances.len());
+        for i in 0..blas.instances.len() {
+            let aabb = blas.instance_aabb(i);
+            // Instances without geometry can't be hit.
+            if aabb.is_empty() {
+                continue;
+            }
+            aabbs.push(Aabb::new(aabb.min.into(), aabb.max.into()));
+            indices.push(i as u32);
+        }
+
+        if aabbs.is_empty() {
+            // Upload a leaf that can never be hit, since empty buffers can't be bound.
+            self.nodes.push(TLASNode {
+                min: [f32::MAX; 3],
+                left_first: 0,
+                max: [f32::MIN; 3],
+                instance_count: 1,
+            });
+            return;
+        }
+
+        let bvh = PlocSearchDistance::Medium.build(
+            &aabbs,
+            indices,
+            SortPrecision::U64,
+            Self::SEARCH_DEPTH_THRESHOLD,
+        );
+
+        // Leaves built by PLOC always hold a single primitive, referenced by `first_index`.
+        self.nodes.reserve(bvh.nodes.len());
+        for node in &bvh.nodes {
+            self.nodes.push(TLASNode {
+                min: node.aabb.min.to_array(),
+                left_first: node.first_index,
+                max: node.aabb.max.to_array(),
+                instance_count: node.prim_count,
+            });
+        }
+
+        // The traversal pushes at most one node per level.
+        let depth = self.depth();
+        assert!(
+            depth <= Self::STACK_SIZE,
+            "TLAS depth {} exceeds the traversal stack size {}",
+            depth,
+            Self::STACK_SIZE
+        );
+    }
+
+    /// Number of internal nodes on the longest path from the root to a leaf.
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+        while let Some((index, level)) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.instance_count > 0 {
+                depth = depth.max(level);
+                continue;
+            }
+            let left = node.left_first as usize;
+            stack.push((left, level + 1));
+            stack.push((left + 1, level + 1));
+        }
+        depth
+    }
+}
@@ -0,0 +1,724 @@
+use glam::Vec3;
+
+use crate::{
+    BLASArray, BLASKind, BVHNode, BVHPrimitive, Intersection, ProceduralKind, ProceduralPrimitive,
+    Ray, VisibilityMask, TLAS,
+};
+
+// Must match `common.glsl`.
+const EPSILON: f32 = 0.00000001;
+const EPSILON1: f32 = 1.0001;
+
+/// Closest hit returned by [`traverse_cwbvh`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CWBVHHit {
+    pub dist: f32,
+    pub uv: glam::Vec2,
+    /// Index of the primitive in the source mesh.
+    ///
+    /// For procedural entries, index in the entry procedural primitives instead,
+    /// see [`ProceduralPrimitive::original_primitive`].
+    pub primitive: u32,
+}
+
+fn sign_extend_s8x4(i: u32) -> u32 {
+    ((i >> 7) & 0x01010101).wrapping_mul(0xff)
+}
+
+fn find_msb(value: u32) -> u32 {
+    31 - value.leading_zeros()
+}
+
+/// Traverse a single CWBVH, in model space.
+///
+/// This is a line by line port of `traverse_cwbvh` in `intersection_utils.glsl`,
+/// used as a reference implementation on the CPU. Both must be kept in sync.
+///
+/// `nodes` and `primitives` are the slices of the entry, and `t` the maximum
+/// distance of the ray.
+pub fn traverse_cwbvh(
+    nodes: &[BVHNode],
+    primitives: &[BVHPrimitive],
+    origin: Vec3,
+    dir: Vec3,
+    t: f32,
+) -> Option<CWBVHHit> {
+    traverse_cwbvh_with(nodes, origin, dir, t, |index, tmax| {
+        let primitive = &primitives[index as usize];
+
+        let e1 = Vec3::from(primitive.edge_1);
+        let e2 = Vec3::from(primitive.edge_2);
+        let v0 = Vec3::from(primitive.vertex_0);
+        let r = dir.cross(e1);
+        let a = e2.dot(r);
+        if a.abs() < EPSILON {
+            return None;
+        }
+        let f = 1.0 / a;
+        let s = origin - v0;
+        let u = f * s.dot(r);
+        if !(EPSILON..=EPSILON1).contains(&u) {
+            return None;
+        }
+        let q = s.cross(e2);
+        let v = f * dir.dot(q);
+        if v < EPSILON || u + v > EPSILON1 {
+            return None;
+        }
+        let d = f * e1.dot(q);
+        if d <= EPSILON || d >= tmax {
+            return None;
+        }
+        Some((d, glam::Vec2::new(u, v), primitive.original_primitive))
+    })
+}
+
+/// Traverse a single CWBVH built over procedural primitives, in model space.
+///
+/// See [`traverse_cwbvh`].
+pub fn traverse_cwbvh_procedural(
+    nodes: &[BVHNode],
+    procedurals: &[ProceduralPrimitive],
+    origin: Vec3,
+    dir: Vec3,
+    t: f32,
+) -> Option<CWBVHHit> {
+    traverse_cwbvh_with(nodes, origin, dir, t, |index, tmax| {
+        let d = intersect_procedural(&procedurals[index as usize], origin, dir);
+        if d >= tmax {
+            return None;
+        }
+        Some((d, glam::Vec2::ZERO, index))
+    })
+}
+
+/// Port of `intersectProcedural` in `intersection_utils.glsl`.
+fn intersect_procedural(primitive: &ProceduralPrimitive, origin: Vec3, dir: Vec3) -> f32 {
+    let min = Vec3::from(primitive.min);
+    let max = Vec3::from(primitive.max);
+    if primitive.kind == ProceduralKind::Sphere as u32 {
+        let center = (min + max) * 0.5;
+        let radius = (max.x - min.x) * 0.5;
+        let oc = origin - center;
+        let a = dir.dot(dir);
+        let b = oc.dot(dir);
+        let c = oc.dot(oc) - radius * radius;
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return f32::MAX;
+        }
+        let s = discriminant.sqrt();
+        let mut t = (-b - s) / a;
+        if t <= EPSILON {
+            t = (-b + s) / a;
+        }
+        return if t > EPSILON { t } else { f32::MAX };
+    }
+    let inv_dir = Vec3::ONE / dir;
+    let t0 = (min - origin) * inv_dir;
+    let t1 = (max - origin) * inv_dir;
+    let near = t0.min(t1).max_element();
+    let far = t0.max(t1).min_element();
+    if near > far || far <= EPSILON {
+        return f32::MAX;
+    }
+    if near > EPSILON {
+        near
+    } else {
+        far
+    }
+}
+
+/// Port of `intersectAABB` in `intersection_utils.glsl`.
+fn intersect_aabb(origin: Vec3, inv_dir: Vec3, min: Vec3, max: Vec3, t: f32) -> f32 {
+    let t0 = (min - origin) * inv_dir;
+    let t1 = (max - origin) * inv_dir;
+    let near = t0.min(t1).max_element().max(0.0);
+    let far = t0.max(t1).min_element().min(t);
+    if near <= far {
+        near
+    } else {
+        f32::MAX
+    }
+}
+
+/// Walk the nodes, calling `intersect` for each primitive of the visited leaves.
+///
+/// `intersect` receives the index of the primitive in the entry and the current
+/// maximum distance, and returns the distance, barycentrics, and id of the hit.
+fn traverse_cwbvh_with<F>(
+    nodes: &[BVHNode],
+    origin: Vec3,
+    dir: Vec3,
+    t: f32,
+    mut intersect: F,
+) -> Option<CWBVHHit>
+where
+    F: FnMut(u32, f32) -> Option<(f32, glam::Vec2, u32)>,
+{
+    let inv_dir = Vec3::ONE / dir;
+
+    let mut stack: Vec<(u32, u32)> = Vec::with_capacity(32);
+    let mut hit_addr = 0;
+    let mut uv = glam::Vec2::ZERO;
+    let mut tmax = t;
+
+    let octant = (if dir.x < 0.0 { 4 } else { 0 })
+        | (if dir.y < 0.0 { 2 } else { 0 })
+        | (if dir.z < 0.0 { 1 } else { 0 });
+    let octinv4: u32 = (7 - octant) * 0x01010101;
+
+    let mut ngroup: (u32, u32) = (0, 1 << 31);
+    let mut tgroup: (u32, u32);
+
+    loop {
+        if ngroup.1 > 0x00FFFFFF {
+            let hits = ngroup.1;
+            let imask = ngroup.1;
+            let child_bit_index = find_msb(hits);
+            let child_node_base_index = ngroup.0;
+            ngroup.1 &= !(1 << child_bit_index);
+            if ngroup.1 > 0x00FFFFFF {
+                stack.push(ngroup);
+            }
+
+            let slot_index = (child_bit_index - 24) ^ (octinv4 & 255);
+            let relative_index = (imask & !(0xFFFFFFFFu32 << slot_index)).count_ones();
+            let child_node_index = child_node_base_index + relative_index;
+
+            let node = &nodes[child_node_index as usize];
+
+            ngroup.0 = node.child_base_idx;
+            tgroup = (node.primitive_base_idx, 0);
+            let mut hitmask: u32 = 0;
+
+            let scale = Vec3::new(
+                f32::from_bits(((node.exyz[0] as u32 + 127) & 0xFF) << 23),
+                f32::from_bits(((node.exyz[1] as u32 + 127) & 0xFF) << 23),
+                f32::from_bits(((node.exyz[2] as u32 + 127) & 0xFF) << 23),
+            );
+            let idir = scale * inv_dir;
+            let orig = (Vec3::from(node.min) - origin) * inv_dir;
+
+            let child_meta = u64::from_le_bytes(node.child_meta);
+            for half in 0..2 {
+                let meta4 = (child_meta >> (half * 32)) as u32;
+                let is_inner4 = (meta4 & (meta4 << 1)) & 0x10101010;
+                let inner_mask4 = sign_extend_s8x4(is_inner4 << 3);
+                let bit_index4 = (meta4 ^ (octinv4 & inner_mask4)) & 0x1F1F1F1F;
+                let child_bits4 = (meta4 >> 5) & 0x07070707;
+
+                for j in 0..4 {
+                    let i = half * 4 + j;
+                    let (lox, hix) = if inv_dir.x < 0.0 {
+                        (node.qhi_x[i], node.qlo_x[i])
+                    } else {
+                        (node.qlo_x[i], node.qhi_x[i])
+                    };
+                    let (loy, hiy) = if inv_dir.y < 0.0 {
+                        (node.qhi_y[i], node.qlo_y[i])
+                    } else {
+                        (node.qlo_y[i], node.qhi_y[i])
+                    };
+                    let (loz, hiz) = if inv_dir.z < 0.0 {
+                        (node.qhi_z[i], node.qlo_z[i])
+                    } else {
+                        (node.qlo_z[i], node.qhi_z[i])
+                    };
+                    let tmin = Vec3::new(lox as f32, loy as f32, loz as f32) * idir + orig;
+                    let tmaxs = Vec3::new(hix as f32, hiy as f32, hiz as f32) * idir + orig;
+                    let cmin = tmin.max_element().max(0.0);
+                    let cmax = tmaxs.min_element().min(tmax);
+                    if cmin <= cmax {
+                        let shift = j * 8;
+                        let bits = (child_bits4 >> shift) & 255;
+                        hitmask |= bits << ((bit_index4 >> shift) & 31);
+                    }
+                }
+            }
+
+            let mask = node.imask as u32;
+            ngroup.1 = (hitmask & 0xFF000000) | mask;
+            tgroup.1 = hitmask & 0x00FFFFFF;
+        } else {
+            tgroup = ngroup;
+            ngroup = (0, 0);
+        }
+
+        while tgroup.1 != 0 {
+            let triangle_index = find_msb(tgroup.1);
+            tgroup.1 -= 1 << triangle_index;
+
+            // `primitive_base_idx` is expressed in `vec4`, with three `vec4` per primitive.
+            if let Some((d, hit_uv, id)) = intersect(tgroup.0 / 3 + triangle_index, tmax) {
+                uv = hit_uv;
+                tmax = d;
+                hit_addr = id;
+            }
+        }
+
+        if ngroup.1 <= 0x00FFFFFF {
+            match stack.pop() {
+                Some(group) => ngroup = group,
+                None => break,
+            }
+        }
+    }
+
+    if tmax < t {
+        Some(CWBVHHit {
+            dist: tmax,
+            uv,
+            primitive: hit_addr,
+        })
+    } else {
+        None
+    }
+}
+
+impl BLASArray {
+    /// Intersect the entry at index `entry_index`, in model space.
+    pub fn intersect_entry(
+        &self,
+        entry_index: usize,
+        origin: Vec3,
+        dir: Vec3,
+        t: f32,
+    ) -> Option<CWBVHHit> {
+        let nodes = &self.nodes[self.entry_nodes(entry_index)];
+        if nodes.is_empty() {
+            return None;
+        }
+        match self.entries[entry_index].kind {
+            BLASKind::Triangles => traverse_cwbvh(
+                nodes,
+                &self.primitives[self.entry_primitives(entry_index)],
+                origin,
+                dir,
+                t,
+            ),
+            BLASKind::Procedural => traverse_cwbvh_procedural(
+                nodes,
+                &self.procedurals[self.entry_procedurals(entry_index)],
+                origin,
+                dir,
+                t,
+            ),
+        }
+    }
+
+    /// Find the closest intersection of a world space ray with the instances.
+    ///
+    /// Results match the GPU `sceneHit` function, without alpha testing. Instances
+    /// are visited linearly, which is fine for validation and sparse queries,
+    /// see [`TLAS::intersect`] otherwise.
+    pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let origin = ray.origin();
+        let dir = ray.dir();
+        let mask = ray.mask();
+
+        let mut closest: Option<Intersection> = None;
+        let mut dist = f32::MAX;
+        for i in 0..self.instances.len() {
+            if let Some(hit) = self.intersect_instance(i, origin, dir, mask, dist) {
+                dist = hit.dist();
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    /// Intersect the instance at index `instance_index` with a world space ray,
+    /// ignoring hits further than `t`.
+    fn intersect_instance(
+        &self,
+        instance_index: usize,
+        origin: Vec3,
+        dir: Vec3,
+        mask: VisibilityMask,
+        t: f32,
+    ) -> Option<Intersection> {
+        let instance = &self.instances[instance_index];
+        if !instance.visibility().intersects(mask) {
+            return None;
+        }
+        let entry = self.entry_index(instance)?;
+        // Performs intersection in model space.
+        let model_origin = instance.world_to_model.project_point3(origin);
+        let model_dir = instance.world_to_model.transform_vector3(dir);
+        let hit = self.intersect_entry(entry, model_origin, model_dir, t)?;
+        // Procedural hits index the primitive, triangle hits its first vertex.
+        let index = match self.entries[entry].kind {
+            BLASKind::Triangles => hit.primitive * 3,
+            BLASKind::Procedural => hit.primitive,
+        };
+        Some(Intersection::new(
+            hit.dist,
+            hit.uv,
+            index,
+            instance_index as u32,
+            instance.material_index,
+        ))
+    }
+}
+
+impl TLAS {
+    /// Find the closest intersection of a world space ray with the instances of `blas`.
+    ///
+    /// This is a port of `sceneHit` in `intersection_utils.glsl`, without alpha
+    /// testing. The TLAS must be built for `blas`.
+    pub fn intersect(&self, blas: &BLASArray, ray: &Ray) -> Option<Intersection> {
+        let origin = ray.origin();
+        let dir = ray.dir();
+        let inv_dir = Vec3::ONE / dir;
+        let node_dist = |index: u32, t: f32| {
+            let node = &self.nodes[index as usize];
+            intersect_aabb(origin, inv_dir, node.min.into(), node.max.into(), t)
+        };
+
+        let mut closest: Option<Intersection> = None;
+        let mut dist = f32::MAX;
+        if node_dist(0, dist) == f32::MAX {
+            return None;
+        }
+
+        // Traverse the TLAS, visiting the nearest child first. Nodes are
+        // stacked with their entry distance.
+        let mut stack: Vec<(u32, f32)> = Vec::with_capacity(Self::STACK_SIZE);
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index as usize];
+            if node.is_leaf() {
+                let instance = node.left_first as usize;
+                if let Some(hit) = blas.intersect_instance(instance, origin, dir, ray.mask(), dist)
+                {
+                    dist = hit.dist();
+                    closest = Some(hit);
+                }
+            } else {
+                let (mut left, mut right) = (node.left_first, node.left_first + 1);
+                let (mut dist_left, mut dist_right) =
+                    (node_dist(left, dist), node_dist(right, dist));
+                if dist_left > dist_right {
+                    std::mem::swap(&mut left, &mut right);
+                    std::mem::swap(&mut dist_left, &mut dist_right);
+                }
+                if dist_left != f32::MAX {
+                    node_index = left;
+                    if dist_right != f32::MAX {
+                        stack.push((right, dist_right));
+                    }
+                    continue;
+                }
+            }
+
+            // Skip the nodes entered further than the closest hit found since they were pushed.
+            while stack.last().is_some_and(|&(_, entry)| entry > dist) {
+                stack.pop();
+            }
+            match stack.pop() {
+                Some((index, _)) => node_index = index,
+                None => break,
+            }
+        }
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec3};
+
+    use crate::{
+        BLASArray, BLASBuilder, BlasBuildOptions, BlasBuildQuality, Intersection, MeshDescriptor,
+        Ray, TLAS,
+    };
+
+    /// Deterministic xorshift generator, in `[0, 1)`.
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+
+        fn vec3(&mut self, scale: f32) -> Vec3 {
+            (Vec3::new(self.next(), self.next(), self.next()) * 2.0 - 1.0) * scale
+        }
+    }
+
+    fn triangles(rng: &mut Random, count: usize) -> Vec<[f32; 4]> {
+        let mut positions = Vec::with_capacity(count * 3);
+        for _ in 0..count {
+            let center = rng.vec3(4.0);
+            for _ in 0..3 {
+                let p = center + rng.vec3(0.75);
+                positions.push([p.x, p.y, p.z, 0.0]);
+            }
+        }
+        positions
+    }
+
+    /// Möller–Trumbore intersection, returns the distance along `dir`.
+    fn intersect_triangle(origin: Vec3, dir: Vec3, v0: Vec3, v1: Vec3, v2: Vec3) -> Option<f32> {
+        let e1 = v1 - v0;
+        let e2 = v2 - v0;
+        let p = dir.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = origin - v0;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(e1);
+        let v = dir.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = e2.dot(q) * inv_det;
+        (t > 0.0).then_some(t)
+    }
+
+    fn world_positions(positions: &[[f32; 4]], model_to_world: Mat4) -> Vec<Vec3> {
+        positions
+            .iter()
+            .map(|p| model_to_world.transform_point3(Vec3::new(p[0], p[1], p[2])))
+            .collect()
+    }
+
+    /// Cast random rays at `target`, and compare `intersect` against brute force
+    /// over the world space triangles of each instance.
+    fn check_intersect(
+        intersect: impl Fn(&Ray) -> Option<Intersection>,
+        world: &[Vec<Vec3>],
+        target: impl Fn(&mut Random) -> Vec3,
+        rng: &mut Random,
+        label: &str,
+    ) {
+        let mut hits = 0;
+        for _ in 0..512 {
+            let origin = rng.vec3(12.0);
+            let target = target(rng);
+            let dir = (target - origin).normalize();
+
+            let expected = world
+                .iter()
+                .flat_map(|triangles| triangles.chunks_exact(3))
+                .filter_map(|v| intersect_triangle(origin, dir, v[0], v[1], v[2]))
+                .min_by(|a, b| a.total_cmp(b));
+
+            let hit = intersect(&Ray::from_origin_dir(&origin, dir));
+            match (hit, expected) {
+                (None, None) => {}
+                (Some(hit), Some(dist)) => {
+                    hits += 1;
+                    assert!(
+                        (hit.dist() - dist).abs() <= 1e-3 * dist.max(1.0),
+                        "{}: distance {} != {}",
+                        label,
+                        hit.dist(),
+                        dist
+                    );
+                    // Nearly coplanar triangles might swap, validate the hit itself.
+                    let start = hit.index() as usize;
+                    let v = &world[hit.instance() as usize][start..start + 3];
+                    let t = intersect_triangle(origin, dir, v[0], v[1], v[2]);
+                    assert!(
+                        t.is_some_and(|t| (t - dist).abs() <= 1e-3 * dist.max(1.0)),
+                        "{}: triangle {} of instance {} isn't the closest hit",
+                        label,
+                        start / 3,
+                        hit.instance()
+                    );
+                }
+                (hit, expected) => panic!(
+                    "{}: got {:?}, expected {:?}",
+                    label,
+                    hit.map(|h| h.dist()),
+                    expected
+                ),
+            }
+        }
+        assert!(hits > 0, "{}: no ray hit the mesh", label);
+    }
+
+    fn check_backend(backend: BLASBuilder) {
+        let mut rng = Random(0x9e3779b9);
+        let positions = triangles(&mut rng, 128);
+        let model_to_world = Mat4::from_scale_rotation_translation(
+            Vec3::splat(1.5),
+            Quat::from_euler(glam::EulerRot::XYZ, 0.3, -0.7, 1.1),
+            Vec3::new(2.0, -1.0, 0.5),
+        );
+        let world = [world_positions(&positions, model_to_world)];
+
+        for quality in [
+            BlasBuildQuality::Fast,
+            BlasBuildQuality::Medium,
+            BlasBuildQuality::High,
+            BlasBuildQuality::SpatialSplits,
+        ]
+        .iter()
+        .copied()
+        .filter(|quality| backend.supports(*quality))
+        {
+            let options = BlasBuildOptions {
+                backend,
+                quality,
+                ..Default::default()
+            };
+            let mut blas = BLASArray::new();
+            blas.add_bvh(
+                MeshDescriptor {
+                    positions: pas::Slice::new(&positions, 0),
+                    normals: None,
+                    texcoords0: None,
+                    tangents: None,
+                },
+                &options,
+            );
+            blas.add_instance(0, model_to_world, 0);
+            check_intersect(
+                |ray| blas.intersect(ray),
+                &world,
+                |rng| model_to_world.transform_point3(rng.vec3(5.0)),
+                &mut rng,
+                &format!("{:?} {:?}", backend, quality),
+            );
+        }
+    }
+
+    /// Deform a mesh past its original bounds, and check the refitted nodes
+    /// still contain every triangle.
+    fn check_refit(backend: BLASBuilder) {
+        let mut rng = Random(0x85ebca6b);
+        let positions = triangles(&mut rng, 128);
+        let deformed: Vec<[f32; 4]> = positions
+            .iter()
+            .map(|p| {
+                let v = Vec3::new(p[0], p[1], p[2]);
+                let wave = Vec3::new((v.y * 1.3).sin(), (v.z * 0.7).sin(), (v.x * 1.9).sin());
+                let v = v * 1.25 + wave * 1.5;
+                [v.x, v.y, v.z, 0.0]
+            })
+            .collect();
+        let model_to_world = Mat4::from_rotation_translation(
+            Quat::from_euler(glam::EulerRot::XYZ, -0.4, 0.9, 0.2),
+            Vec3::new(-1.0, 0.5, 2.0),
+        );
+        let world = [world_positions(&deformed, model_to_world)];
+
+        for quality in [BlasBuildQuality::Medium, BlasBuildQuality::SpatialSplits]
+            .iter()
+            .copied()
+        {
+            let options = BlasBuildOptions {
+                backend,
+                quality,
+                ..Default::default()
+            };
+            let mut blas = BLASArray::new();
+            blas.add_bvh(
+                MeshDescriptor {
+                    positions: pas::Slice::new(&positions, 0),
+                    normals: None,
+                    texcoords0: None,
+                    tangents: None,
+                },
+                &options,
+            );
+            blas.add_instance(0, model_to_world, 0);
+            blas.refit_entry(0, pas::Slice::new(&deformed, 0));
+            check_intersect(
+                |ray| blas.intersect(ray),
+                &world,
+                |rng| model_to_world.transform_point3(rng.vec3(7.0)),
+                &mut rng,
+                &format!("refit {:?} {:?}", backend, quality),
+            );
+        }
+    }
+
+    #[test]
+    fn tlas_matches_brute_force() {
+        let mut rng = Random(0xc2b2ae35);
+        let meshes = [triangles(&mut rng, 64), triangles(&mut rng, 96)];
+
+        let mut blas = BLASArray::new();
+        for positions in &meshes {
+            blas.add_bvh(
+                MeshDescriptor {
+                    positions: pas::Slice::new(positions, 0),
+                    normals: None,
+                    texcoords0: None,
+                    tangents: None,
+                },
+                &BlasBuildOptions::default(),
+            );
+        }
+
+        // Overlapping instances, so that the TLAS visits nodes behind the closest hit.
+        let mut world = Vec::new();
+        let mut translations = Vec::new();
+        for i in 0..12 {
+            let translation = rng.vec3(8.0);
+            let model_to_world = Mat4::from_scale_rotation_translation(
+                Vec3::splat(0.5 + rng.next()),
+                Quat::from_euler(
+                    glam::EulerRot::XYZ,
+                    rng.next() * 6.0,
+                    rng.next() * 6.0,
+                    rng.next() * 6.0,
+                ),
+                translation,
+            );
+            let entry = i % meshes.len();
+            blas.add_instance(entry as u32, model_to_world, 0);
+            world.push(world_positions(&meshes[entry], model_to_world));
+            translations.push(translation);
+        }
+        let tlas = TLAS::new(&blas);
+
+        let target = |rng: &mut Random| {
+            let instance = (rng.next() * translations.len() as f32) as usize;
+            translations[instance] + rng.vec3(3.0)
+        };
+        check_intersect(
+            |ray| tlas.intersect(&blas, ray),
+            &world,
+            target,
+            &mut rng,
+            "TLAS",
+        );
+        check_intersect(|ray| blas.intersect(ray), &world, target, &mut rng, "BLAS");
+    }
+
+    #[cfg(feature = "tinybvh")]
+    #[test]
+    fn intersect_matches_brute_force_tinybvh() {
+        check_backend(BLASBuilder::TinyBVH);
+    }
+
+    #[test]
+    fn intersect_matches_brute_force_obvhs() {
+        check_backend(BLASBuilder::Obvhs);
+    }
+
+    #[cfg(feature = "tinybvh")]
+    #[test]
+    fn refit_matches_brute_force_tinybvh() {
+        check_refit(BLASBuilder::TinyBVH);
+    }
+
+    #[test]
+    fn refit_matches_brute_force_obvhs() {
+        check_refit(BLASBuilder::Obvhs);
+    }
+}
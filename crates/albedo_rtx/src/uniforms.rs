@@ -1,4 +1,6 @@
 use albedo_backend::{gpu, mesh};
+use albedo_math::AABB;
+use bitflags::bitflags;
 use bytemuck::{Pod, Zeroable};
 
 use glam::Vec4Swizzles;
@@ -12,33 +14,124 @@ pub trait Uniform: Sized {
     }
 }
 
+bitflags! {
+    /// Ray types an instance is visible to.
+    ///
+    /// An instance is only intersected if its mask overlaps the mask of the ray.
+    /// Bits above [`VisibilityMask::PICKING`] are free for application use.
+    ///
+    /// Must match the `VISIBILITY_*` defines in `structures.glsl`.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct VisibilityMask: u8 {
+        const CAMERA = 0b00000001;
+        const INDIRECT = 0b00000010;
+        const SHADOW = 0b00000100;
+        const PICKING = 0b00001000;
+        const _ = !0;
+    }
+}
+
+impl Default for VisibilityMask {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+bitflags! {
+    /// Must match the `INSTANCE_*` defines in `structures.glsl`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct InstanceFlags: u32 {
+        /// Skip alpha testing, even if the material has an alpha cutoff.
+        const OPAQUE = 0b00000001;
+        /// Instance references a [`crate::BLASKind::Procedural`] entry.
+        ///
+        /// Set by [`crate::BLASArray::add_instance`].
+        const PROCEDURAL = 0b00000010;
+    }
+}
+
 #[repr(C)]
-#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Instance {
     pub model_to_world: glam::Mat4,
     pub world_to_model: glam::Mat4,
+    /// Transform of the previous frame, used to compute motion vectors.
+    pub previous_model_to_world: glam::Mat4,
     pub material_index: u32,
     // @todo: migrate those parameter to an SSBO of offsets.
     pub bvh_root_index: u32,
     pub vertex_root_index: u32,
     pub bvh_primitive_index: u32,
+    /// [`VisibilityMask`] bits, widened for the GPU.
+    pub mask: u32,
+    /// [`InstanceFlags`] bits.
+    pub flags: u32,
+    /// Index of the [`crate::BLASEntryDescriptor`], set by [`crate::BLASArray::add_instance`].
+    pub entry_index: u32,
+    pub padding: u32,
 }
 impl Uniform for Instance {}
 
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            model_to_world: glam::Mat4::IDENTITY,
+            world_to_model: glam::Mat4::IDENTITY,
+            previous_model_to_world: glam::Mat4::IDENTITY,
+            material_index: 0,
+            bvh_root_index: 0,
+            vertex_root_index: 0,
+            bvh_primitive_index: 0,
+            mask: VisibilityMask::all().bits() as u32,
+            flags: 0,
+            entry_index: 0,
+            padding: 0,
+        }
+    }
+}
+
 impl Instance {
     pub fn from_transform(model_to_world: glam::Mat4) -> Self {
         let world_to_model = model_to_world.inverse();
         Self {
             model_to_world,
             world_to_model,
+            previous_model_to_world: model_to_world,
             ..Default::default()
         }
     }
 
+    /// Set the transform of the current frame.
+    ///
+    /// The previous transform is left untouched, see [`Instance::store_previous_transform`].
     pub fn set_transform(&mut self, model_to_world: glam::Mat4) {
         self.model_to_world = model_to_world;
         self.world_to_model = self.model_to_world.inverse();
     }
+
+    /// Copy the current transform into the previous one.
+    ///
+    /// Must be called once per frame, before the transform is updated.
+    /// Instances that aren't moving are then reprojected without motion.
+    pub fn store_previous_transform(&mut self) {
+        self.previous_model_to_world = self.model_to_world;
+    }
+
+    pub fn visibility(&self) -> VisibilityMask {
+        VisibilityMask::from_bits_retain(self.mask as u8)
+    }
+
+    pub fn set_visibility(&mut self, mask: VisibilityMask) {
+        self.mask = mask.bits() as u32;
+    }
+
+    pub fn flags(&self) -> InstanceFlags {
+        InstanceFlags::from_bits_retain(self.flags)
+    }
+
+    pub fn set_flags(&mut self, flags: InstanceFlags) {
+        self.flags = flags.bits();
+    }
 }
 
 #[repr(C)]
@@ -49,6 +142,37 @@ pub struct Material {
     pub reflectivity: f32,
     pub albedo_texture: u32,
     pub mra_texture: u32,
+    /// Hits with an alpha below this threshold are ignored during traversal.
+    ///
+    /// Alpha is read from `color` and the albedo texture. `0.0` for opaque materials.
+    pub alpha_cutoff: f32,
+    /// Tangent space normal map, requires vertex tangents.
+    pub normal_texture: u32,
+    /// Multiplied with `emissive`, sRGB encoded.
+    pub emissive_texture: u32,
+    /// Fraction of the non-metallic light refracted through the surface, in `[0; 1]`.
+    pub transmission: f32,
+    /// Emitted color, in linear space.
+    pub emissive: glam::Vec3,
+    pub emissive_strength: f32,
+    /// Absorption coefficient of the volume enclosed by the surface, per unit of distance.
+    ///
+    /// Only applied to transmissive materials, see [`Material::set_attenuation`].
+    pub attenuation: glam::Vec3,
+    /// Index of refraction.
+    pub ior: f32,
+    /// Strength of the clearcoat layer, in `[0; 1]`.
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub sheen_roughness: f32,
+    /// Strength of the specular anisotropy, in `[0; 1]`.
+    pub anisotropy_strength: f32,
+    /// Color of the sheen layer, in linear space. Black disables the sheen.
+    pub sheen_color: glam::Vec3,
+    /// Rotation of the anisotropy direction from the tangent, in radians.
+    ///
+    /// Meshes without tangent use an arbitrary direction.
+    pub anisotropy_rotation: f32,
 }
 unsafe impl bytemuck::Pod for Material {}
 unsafe impl bytemuck::Zeroable for Material {}
@@ -62,9 +186,40 @@ impl Material {
             reflectivity,
             albedo_texture: INVALID_INDEX,
             mra_texture: INVALID_INDEX,
+            normal_texture: INVALID_INDEX,
+            emissive_texture: INVALID_INDEX,
+            ior: 1.5,
             ..Default::default()
         }
     }
+
+    pub fn is_transmissive(&self) -> bool {
+        self.transmission > 0.0
+    }
+
+    /// Set the absorption from the color reached by white light after `distance`
+    /// in the medium, as `KHR_materials_volume` does.
+    pub fn set_attenuation(&mut self, color: glam::Vec3, distance: f32) {
+        let color = color.max(glam::Vec3::splat(f32::EPSILON));
+        self.attenuation = if distance.is_finite() && distance > 0.0 {
+            -color.min(glam::Vec3::ONE).map(f32::ln) / distance
+        } else {
+            glam::Vec3::ZERO
+        };
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.alpha_cutoff <= 0.0
+    }
+
+    /// Emitted radiance, without the emissive texture.
+    pub fn emission(&self) -> glam::Vec3 {
+        self.emissive * self.emissive_strength
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission().max_element() > 0.0
+    }
 }
 
 #[repr(C)]
@@ -72,6 +227,10 @@ impl Material {
 pub struct Vertex {
     pub position: [f32; 4],
     pub normal: [f32; 4],
+    /// Tangent, with the bitangent handedness in `w`.
+    ///
+    /// Zero if the mesh has no tangent.
+    pub tangent: [f32; 4],
 }
 unsafe impl bytemuck::Pod for Vertex {}
 unsafe impl bytemuck::Zeroable for Vertex {}
@@ -85,6 +244,7 @@ impl Vertex {
         Vertex {
             position: [position[0], position[1], position[2], uv[0]],
             normal: [normal[0], normal[1], normal[2], uv[1]],
+            tangent: [0.0; 4],
         }
     }
 
@@ -95,7 +255,7 @@ impl Vertex {
 
 impl mesh::AsVertexFormat for Vertex {
     fn as_vertex_formats() -> &'static [mesh::AttributeDescriptor] {
-        static ATTRIBUTE_DESCRIPTORS: [mesh::AttributeDescriptor; 2] = [
+        static ATTRIBUTE_DESCRIPTORS: [mesh::AttributeDescriptor; 3] = [
             mesh::AttributeDescriptor {
                 id: mesh::AttributeId::POSITION,
                 format: wgpu::VertexFormat::Float32x4,
@@ -104,11 +264,62 @@ impl mesh::AsVertexFormat for Vertex {
                 id: mesh::AttributeId::NORMAL,
                 format: wgpu::VertexFormat::Float32x4,
             },
+            mesh::AttributeDescriptor {
+                id: mesh::AttributeId::TANGENT,
+                format: wgpu::VertexFormat::Float32x4,
+            },
         ];
         &ATTRIBUTE_DESCRIPTORS
     }
 }
 
+/// Emissive triangle, in world space.
+///
+/// Built by [`crate::BLASArray::emissive_triangles`], e.g., to list or export
+/// the mesh lights of a scene.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct EmissiveTriangle {
+    pub v0: [f32; 3],
+    pub instance: u32,
+    pub v1: [f32; 3],
+    /// Index of the first vertex, relative to the instance vertex root.
+    ///
+    /// Matches [`Intersection::index`].
+    pub index: u32,
+    pub v2: [f32; 3],
+    pub area: f32,
+    /// Emitted radiance, without the emissive texture.
+    pub emission: [f32; 3],
+}
+
+/// Type of a [`Light`].
+///
+/// Must match the `LIGHT_*` defines in `structures.glsl`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightKind {
+    /// Rectangle, emitting on the side of the normal.
+    #[default]
+    Rect = 0,
+    /// Infinitely far light, e.g., the sun.
+    Directional = 1,
+    Point = 2,
+    Spot = 3,
+}
+
+/// Light source.
+///
+/// The light position is packed in `normal.w`, `tangent.w`, and `bitangent.w`.
+/// Other attributes depend on the [`LightKind`]:
+///
+/// - `Rect`: `tangent` and `bitangent` are the edges of the rectangle, the
+///   position is its corner, and `intensity` the emitted radiance
+/// - `Directional`: `normal` is the direction of the light, `intensity` the
+///   illuminance, and `cos_outer` the cosine of half the angular diameter
+/// - `Point`: `intensity` is the luminous intensity
+/// - `Spot`: `normal` is the direction of the cone, `intensity` the luminous
+///   intensity, and `cos_inner`/`cos_outer` the cosines of the cone angles
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
 pub struct Light {
@@ -116,9 +327,10 @@ pub struct Light {
     pub tangent: glam::Vec4,
     pub bitangent: glam::Vec4,
     pub intensity: f32,
-    padding_0: u32,
-    padding_1: u32,
-    padding_2: u32,
+    /// [`LightKind`] of the light.
+    pub kind: u32,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
 }
 
 unsafe impl bytemuck::Pod for Light {}
@@ -148,13 +360,84 @@ impl Light {
         }
     }
 
+    /// Create a directional light.
+    ///
+    /// `direction` is the direction the light travels in, and `angular_diameter`
+    /// the apparent size of the light in radians, `0.0` for hard shadows.
+    pub fn directional(direction: glam::Vec3, illuminance: f32, angular_diameter: f32) -> Self {
+        Light {
+            normal: direction.normalize().extend(0.0),
+            intensity: illuminance,
+            kind: LightKind::Directional as u32,
+            cos_outer: (angular_diameter * 0.5).cos(),
+            ..Default::default()
+        }
+    }
+
+    /// Create a point light, emitting uniformly in all directions.
+    pub fn point(position: glam::Vec3, intensity: f32) -> Self {
+        let mut light = Light {
+            intensity,
+            kind: LightKind::Point as u32,
+            ..Default::default()
+        };
+        light.set_position(position);
+        light
+    }
+
+    /// Create a spot light.
+    ///
+    /// Angles are in radians, from the cone axis. They match the `innerConeAngle`
+    /// and `outerConeAngle` properties of `KHR_lights_punctual`.
+    pub fn spot(
+        position: glam::Vec3,
+        direction: glam::Vec3,
+        intensity: f32,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        let mut light = Light {
+            normal: direction.normalize().extend(0.0),
+            intensity,
+            kind: LightKind::Spot as u32,
+            cos_inner: inner_cone_angle.cos(),
+            cos_outer: outer_cone_angle.cos(),
+            ..Default::default()
+        };
+        light.set_position(position);
+        light
+    }
+
+    pub fn kind(&self) -> LightKind {
+        match self.kind {
+            1 => LightKind::Directional,
+            2 => LightKind::Point,
+            3 => LightKind::Spot,
+            _ => LightKind::Rect,
+        }
+    }
+
+    pub fn position(&self) -> glam::Vec3 {
+        glam::Vec3::new(self.normal.w, self.tangent.w, self.bitangent.w)
+    }
+
+    pub fn set_position(&mut self, position: glam::Vec3) {
+        self.normal.w = position.x;
+        self.tangent.w = position.y;
+        self.bitangent.w = position.z;
+    }
+
     pub fn from_matrix(local_to_world: glam::Mat4) -> Self {
         let mut light = Light::new();
         light.set_from_matrix(local_to_world, 1.0, 1.0);
         light
     }
 
+    /// Set a rectangle light from its transform and size.
+    ///
+    /// The light is centered on the origin of `local_to_world`, and emits along its `z` axis.
     pub fn set_from_matrix(&mut self, local_to_world: glam::Mat4, width: f32, height: f32) {
+        self.kind = LightKind::Rect as u32;
         let mut origin = local_to_world.w_axis;
         self.normal = local_to_world * glam::Vec4::new(0.0, 0.0, 1.0, 0.0);
         self.tangent = local_to_world * glam::Vec4::new(width, 0.0, 0.0, 0.0);
@@ -174,9 +457,14 @@ impl Light {
 pub struct PerDrawUniforms {
     pub frame_count: u32,
     pub seed: u32,
+    /// Paths are terminated after this many bounces, `0` for no limit.
     pub bounces: u32,
-    pub padding: u32,
+    /// Bounces before paths can be terminated by russian roulette.
+    pub min_bounces: u32,
     pub dimensions: [u32; 2],
+    /// Sub-pixel offset of the camera rays, see [`Jitter`].
+    pub jitter: u32,
+    pub padding: u32,
 }
 
 impl PerDrawUniforms {
@@ -187,10 +475,103 @@ impl PerDrawUniforms {
     }
 }
 
+/// Sequence of sub-pixel offsets applied to the camera rays.
+///
+/// The offset of each frame is derived from [`PerDrawUniforms::frame_count`].
+/// Stored in [`PerDrawUniforms::jitter`], and used with temporal anti-aliasing,
+/// see [`crate::passes::TaaPass`].
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Jitter {
+    /// Rays go through the pixel corner.
+    #[default]
+    None = 0,
+    /// Halton sequence in base 2 and 3, repeating every 16 frames.
+    Halton = 1,
+    /// R2 low discrepancy sequence.
+    R2 = 2,
+}
+
+impl Jitter {
+    const HALTON_LENGTH: u32 = 16;
+
+    /// Offset of the frame, in pixels, in `[-0.5; 0.5[`.
+    ///
+    /// Matches the offset used by the ray generation, to jitter the
+    /// projection of rasterized previews.
+    pub fn offset(self, frame: u32) -> glam::Vec2 {
+        match self {
+            Self::None => glam::Vec2::ZERO,
+            Self::Halton => {
+                let index = frame % Self::HALTON_LENGTH + 1;
+                glam::Vec2::new(radical_inverse(index, 2), radical_inverse(index, 3))
+                    - glam::Vec2::splat(0.5)
+            }
+            Self::R2 => {
+                // Fixed point, with 0.5 added to start in the middle of the pixel.
+                let x = frame.wrapping_mul(0xC13FA9A9).wrapping_add(0x80000000);
+                let y = frame.wrapping_mul(0x91E10DA5).wrapping_add(0x80000000);
+                glam::Vec2::new(x as f32, y as f32) / 4294967296.0 - glam::Vec2::splat(0.5)
+            }
+        }
+    }
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        f /= base as f32;
+        result += f * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
 unsafe impl bytemuck::Pod for PerDrawUniforms {}
 unsafe impl bytemuck::Zeroable for PerDrawUniforms {}
 impl Uniform for PerDrawUniforms {}
 
+/// Edge-stopping and history parameters of the SVGF denoiser.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SvgfParameters {
+    /// Depth edge-stopping of the spatial filters.
+    pub phi_depth: f32,
+    /// Exponent of the normal edge-stopping of the spatial filters.
+    pub phi_normal: f32,
+    /// Luminance edge-stopping of the à-trous filter, relative to the standard deviation.
+    pub phi_luminance: f32,
+    /// Relative depth difference above which the history is discarded.
+    pub depth_threshold: f32,
+    /// Cosine between normals below which the history is discarded.
+    pub normal_threshold: f32,
+    /// Maximum number of accumulated frames.
+    pub max_history: u32,
+    /// History length below which the variance is estimated spatially.
+    pub variance_history: u32,
+    pub padding: u32,
+}
+
+impl Default for SvgfParameters {
+    fn default() -> Self {
+        Self {
+            phi_depth: 1.0,
+            phi_normal: 128.0,
+            phi_luminance: 4.0,
+            depth_threshold: 0.1,
+            normal_threshold: 0.7,
+            max_history: 128,
+            variance_history: 4,
+            padding: 0,
+        }
+    }
+}
+
+unsafe impl bytemuck::Pod for SvgfParameters {}
+unsafe impl bytemuck::Zeroable for SvgfParameters {}
+impl Uniform for SvgfParameters {}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct Camera {
@@ -247,12 +628,19 @@ unsafe impl bytemuck::Pod for Camera {}
 unsafe impl bytemuck::Zeroable for Camera {}
 impl Uniform for Camera {}
 
+/// GPU ray payload.
+///
+/// `terminated[2]` holds the [`VisibilityMask`] of the ray.
+///
+/// When denoising, `radiance` and `specular` hold the diffuse and specular
+/// signals respectively.
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct Ray {
     origin: glam::Vec4,
     dir: glam::Vec4,
     radiance: glam::Vec4,
+    specular: glam::Vec4,
     terminated: [u32; 4],
 }
 unsafe impl bytemuck::Pod for Ray {}
@@ -265,7 +653,8 @@ impl Ray {
             origin: glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
             dir: glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
             radiance: glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
-            terminated: [0, 0, 0, 0],
+            specular: glam::Vec4::ZERO,
+            terminated: [0, 0, VisibilityMask::all().bits() as u32, 0],
         }
     }
 
@@ -274,15 +663,53 @@ impl Ray {
             origin: glam::Vec4::new(origin.x, origin.y, origin.z, 1.0),
             dir: glam::Vec4::new(direction.x, direction.y, direction.z, 1.0),
             radiance: glam::Vec4::new(0.0, 0.0, 0.0, 1.0),
-            terminated: [0, 0, 0, 0],
+            specular: glam::Vec4::ZERO,
+            terminated: [0, 0, VisibilityMask::all().bits() as u32, 0],
         }
     }
 
     pub fn throughput(&self) -> glam::Vec3 {
         glam::Vec3::new(self.origin.w, self.dir.w, self.radiance.w)
     }
+
+    pub fn origin(&self) -> glam::Vec3 {
+        self.origin.truncate()
+    }
+
+    pub fn dir(&self) -> glam::Vec3 {
+        self.dir.truncate()
+    }
+
+    pub fn mask(&self) -> VisibilityMask {
+        VisibilityMask::from_bits_retain(self.terminated[2] as u8)
+    }
+
+    pub fn set_mask(&mut self, mask: VisibilityMask) {
+        self.terminated[2] = mask.bits() as u32;
+    }
 }
 
+/// Shadow ray, traced towards a light sample.
+///
+/// Written by the shading pass when next event estimation is enabled,
+/// and traced by [`crate::passes::ShadowPass`].
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ShadowRay {
+    /// Origin, with the distance to the light in `w`.
+    origin: glam::Vec4,
+    dir: glam::Vec4,
+    /// Contribution added to the path if the light is visible.
+    radiance: glam::Vec4,
+    /// Radiance gathered by the shading pass, with the bounce in `w`.
+    ///
+    /// Completed by the shadow pass for [`crate::passes::AovFlags::BOUNCE_RADIANCE`].
+    bounce_radiance: glam::Vec4,
+}
+unsafe impl bytemuck::Pod for ShadowRay {}
+unsafe impl bytemuck::Zeroable for ShadowRay {}
+impl Uniform for ShadowRay {}
+
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
 pub struct Intersection {
@@ -295,6 +722,40 @@ pub struct Intersection {
     padding_0: f32,
 }
 
+impl Intersection {
+    pub(crate) fn new(dist: f32, uv: glam::Vec2, index: u32, instance: u32, material: u32) -> Self {
+        Self {
+            uv,
+            index,
+            instance,
+            material_index: material,
+            emitter: INVALID_INDEX,
+            dist,
+            padding_0: 0.0,
+        }
+    }
+
+    /// Barycentric coordinates of the hit, relative to the second and third vertices.
+    pub fn uv(&self) -> glam::Vec2 {
+        self.uv
+    }
+    /// Index of the first vertex of the hit triangle, relative to the instance vertex start.
+    ///
+    /// For procedural instances, index of the hit primitive, relative to the instance start.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+    pub fn instance(&self) -> u32 {
+        self.instance
+    }
+    pub fn material_index(&self) -> u32 {
+        self.material_index
+    }
+    pub fn dist(&self) -> f32 {
+        self.dist
+    }
+}
+
 unsafe impl bytemuck::Pod for Intersection {}
 unsafe impl bytemuck::Zeroable for Intersection {}
 impl Uniform for Intersection {}
@@ -353,9 +814,28 @@ unsafe impl bytemuck::Zeroable for TextureInfo {}
 impl Uniform for TextureInfo {}
 
 #[repr(C)]
-#[derive(Clone, Copy, Default, Pod, Zeroable)]
+#[derive(Clone, Copy, Pod, Zeroable)]
 pub struct RadianceParameters {
     pub use_noise_texture: u32,
+    /// Multiplier applied to the probe radiance, `0.25` by default.
+    pub probe_intensity: f32,
+    /// Rotation of the probe around the `y` axis, in radians.
+    pub probe_rotation: f32,
+    /// Non-zero to importance sample the probe at each bounce.
+    ///
+    /// Requires next event estimation, and a [`crate::ProbeDistribution`].
+    pub sample_probe: u32,
+}
+
+impl Default for RadianceParameters {
+    fn default() -> Self {
+        Self {
+            use_noise_texture: 0,
+            probe_intensity: 0.25,
+            probe_rotation: 0.0,
+            sample_probe: 0,
+        }
+    }
 }
 
 #[cfg(feature = "tinybvh")]
@@ -410,9 +890,90 @@ pub struct BVHPrimitive {
 }
 impl Uniform for BVHPrimitive {}
 
+/// Shape of a [`ProceduralPrimitive`].
+///
+/// Must match the `PROCEDURAL_*` defines in `structures.glsl`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProceduralKind {
+    Sphere = 0,
+    Box = 1,
+}
+
+/// Analytic primitive, intersected without tessellation.
+///
+/// Spheres are inscribed in the bounds, which must be a cube.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ProceduralPrimitive {
+    pub min: [f32; 3],
+    /// [`ProceduralKind`] of the primitive.
+    pub kind: u32,
+    pub max: [f32; 3],
+    /// Index of the primitive in the source list, set when building the BVH.
+    pub original_primitive: u32,
+}
+impl Uniform for ProceduralPrimitive {}
+
+impl ProceduralPrimitive {
+    pub fn sphere(center: glam::Vec3, radius: f32) -> Self {
+        Self {
+            min: (center - radius).to_array(),
+            kind: ProceduralKind::Sphere as u32,
+            max: (center + radius).to_array(),
+            original_primitive: 0,
+        }
+    }
+
+    pub fn cuboid(min: glam::Vec3, max: glam::Vec3) -> Self {
+        Self {
+            min: min.to_array(),
+            kind: ProceduralKind::Box as u32,
+            max: max.to_array(),
+            original_primitive: 0,
+        }
+    }
+
+    pub fn aabb(&self) -> AABB {
+        AABB::from_points(self.min.into(), self.max.into())
+    }
+}
+
+/// Binary node of the top-level acceleration structure.
+///
+/// Leaves reference a single instance. Inner nodes store their children
+/// contiguously, at `left_first` and `left_first + 1`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TLASNode {
+    /// AABB min, in world space.
+    pub min: [f32; 3],
+    /// Index of the left child for inner nodes, instance index for leaves.
+    pub left_first: u32,
+    /// AABB max, in world space.
+    pub max: [f32; 3],
+    /// `1` for leaves, `0` for inner nodes.
+    pub instance_count: u32,
+}
+impl Uniform for TLASNode {}
+
+impl TLASNode {
+    /// Returns `true` if the node is a leaf.
+    pub fn is_leaf(&self) -> bool {
+        self.instance_count > 0
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct RaytraceResources<'a> {
     pub rays: gpu::StorageBufferSlice<'a, Ray>,
     pub intersections: gpu::StorageBufferSlice<'a, Intersection>,
+    /// Required when shading with next event estimation.
+    pub shadow_rays: Option<gpu::StorageBufferSlice<'a, ShadowRay>>,
+    /// Required when shading compacted rays, see [`crate::passes::CompactedRays`].
+    pub active_rays: Option<gpu::StorageBufferSlice<'a, u32>>,
+    /// Required when shading with AOVs, see [`crate::passes::AovFlags`].
+    pub aovs: Option<&'a crate::passes::AovTargets>,
     pub global_uniforms: gpu::UniformBufferSlice<'a, PerDrawUniforms>,
     pub camera_uniforms: gpu::UniformBufferSlice<'a, Camera>,
 }
@@ -422,6 +983,9 @@ pub struct DenoiseResources<'a> {
     pub gbuffer_current: &'a wgpu::TextureView,
     pub gbuffer_previous: &'a wgpu::TextureView,
     pub motion: &'a wgpu::TextureView,
+    /// Reflectances of the primary hits, used to remodulate the diffuse
+    /// and specular signals after denoising.
+    pub reflectance: &'a wgpu::TextureView,
 }
 
 impl<'a> DenoiseResources<'a> {
@@ -430,6 +994,50 @@ impl<'a> DenoiseResources<'a> {
             gbuffer_current: self.gbuffer_previous,
             gbuffer_previous: self.gbuffer_current,
             motion: self.motion,
+            reflectance: self.reflectance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Jitter;
+
+    fn assert_offsets(jitter: Jitter, expected: &[(u32, [f32; 2])]) {
+        for &(frame, offset) in expected {
+            let actual = jitter.offset(frame);
+            assert!(
+                (actual - glam::Vec2::from(offset)).abs().max_element() < 1e-6,
+                "{:?} at frame {}: expected {:?}, got {:?}",
+                jitter,
+                frame,
+                offset,
+                actual
+            );
         }
     }
+
+    /// Values computed from `subpixelJitter` in `math.glsl`.
+    #[test]
+    fn jitter_matches_shaders() {
+        assert_offsets(Jitter::None, &[(0, [0.0, 0.0]), (5, [0.0, 0.0])]);
+        assert_offsets(
+            Jitter::Halton,
+            &[
+                (0, [0.0, -1.0 / 6.0]),
+                (1, [-0.25, 1.0 / 6.0]),
+                (2, [0.25, -7.0 / 18.0]),
+                (16, [0.0, -1.0 / 6.0]),
+            ],
+        );
+        assert_offsets(
+            Jitter::R2,
+            &[
+                (0, [0.0, 0.0]),
+                (1, [-0.24512233, -0.43015971]),
+                (2, [-0.49024467, 0.13968058]),
+                (7, [0.28414366, -0.01111796]),
+            ],
+        );
+    }
 }
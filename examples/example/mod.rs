@@ -65,12 +65,13 @@ pub async fn initialize<E>(title: &str) -> (EventLoop, App) {
 
     let optional_features: wgpu::Features = wgpu::Features::default();
     let required_features: wgpu::Features =
-        wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES | wgpu::Features::PUSH_CONSTANTS;
 
     let adapter_features: wgpu::Features = wgpu::Features::default();
     let needed_limits = wgpu::Limits {
-        max_storage_buffers_per_shader_stage: 8,
+        max_storage_buffers_per_shader_stage: 10,
         max_storage_buffer_binding_size: 256 * 1024 * 1024,
+        max_push_constant_size: 64,
         ..wgpu::Limits::default()
     };
     let trace_dir = std::env::var("WGPU_TRACE");
@@ -5,7 +5,7 @@ use albedo_backend::mesh::{shapes, AsVertexFormat, ToPrimitive};
 use albedo_bvh::{builders::SAHBuilder, BLASArray};
 use albedo_rtx::{
     uniforms::{Ray, Vertex},
-    Instance,
+    Instance, VisibilityMask,
 };
 
 use nanorand::Rng;
@@ -158,17 +158,23 @@ impl Example for PickingExample {
             Camera::new(app.surface_config.width as f32 / app.surface_config.height as f32);
 
         const NB_INSTANCES: usize = 100;
+        const HELPER_FREQUENCY: usize = 10;
         let mut instances: Vec<Instance> = Vec::with_capacity(NB_INSTANCES);
         let mut rng = nanorand::WyRand::new_seed(42);
         let mut rand_val = |len: f32| rng.generate::<f32>() * len - 0.5 * len;
         let mut uniforms_data: Vec<Uniforms> = Vec::with_capacity(NB_INSTANCES);
-        for _ in 0..NB_INSTANCES {
+        for i in 0..NB_INSTANCES {
             let local_to_world = glam::Mat4::from_translation(glam::Vec3::new(
                 rand_val(20.0),
                 rand_val(20.0),
                 rand_val(10.0) - 40.0,
             ));
-            instances.push(Instance::from_transform(local_to_world.clone()));
+            let mut instance = Instance::from_transform(local_to_world.clone());
+            // Some instances act as helpers: visible, but not pickable.
+            if i % HELPER_FREQUENCY == 0 {
+                instance.set_visibility(VisibilityMask::all() - VisibilityMask::PICKING);
+            }
+            instances.push(instance);
             uniforms_data.push(Uniforms {
                 transform: camera.perspective * local_to_world,
                 color: glam::Vec4::new(rand_val(1.0), rand_val(1.0), rand_val(1.0), 1.0),
@@ -215,6 +221,9 @@ impl Example for PickingExample {
             None,
         );
         let light_buffer = gpu::Buffer::dummy_storage(&app.device);
+        let procedural_buffer = gpu::Buffer::dummy_storage(&app.device);
+        let tlas = albedo_rtx::TLAS::new(&blas);
+        let tlas_buffer = gpu::Buffer::new_storage_with_data(&app.device, &tlas.nodes, None);
 
         let intersection_pass =
             albedo_rtx::passes::IntersectorPass::new(&app.device, &scene_bgl, None);
@@ -232,6 +241,8 @@ impl Example for PickingExample {
             index_buffer.as_storage_slice().unwrap(),
             vertex_buffer.as_storage_slice().unwrap(),
             light_buffer.as_storage_slice().unwrap(),
+            tlas_buffer.as_storage_slice().unwrap(),
+            procedural_buffer.as_storage_slice().unwrap(),
         );
 
         PickingExample {
@@ -270,7 +281,9 @@ impl Example for PickingExample {
             &mut encoder,
             &self.scene_bindgroup,
             &self.intersection_pass_bg,
+            None,
             (1, 1, 1),
+            VisibilityMask::PICKING,
         );
 
         {